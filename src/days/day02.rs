@@ -1,4 +1,7 @@
 use std::num::ParseIntError;
+use std::str::FromStr;
+
+use anyhow::anyhow;
 
 use crate::util::Answer;
 
@@ -7,29 +10,166 @@ use itertools::Itertools;
 pub fn solve(input: &str) -> anyhow::Result<String> {
     let reports = parse_reports(input)?;
 
-    let p1 = part_one(&reports);
+    let policy = SafetyPolicy::default();
+
+    let p1 = part_one(&reports, &policy);
     assert_eq!(p1, 257); // Known-correct answer
 
-    let p2 = part_two(&reports);
+    let p2 = part_two(&reports, &policy);
     assert_eq!(p2, 328); // Known-correct answer
 
     Answer::first(2, p1).second(p2).report()
 }
 
-fn part_one(reports: &[Vec<i32>]) -> usize {
+/// Count safe reports under a custom safety policy.
+///
+/// Options: `--min-step <n>` and `--max-step <n>` bound the size of each
+/// step between levels, `--trend <increasing|decreasing|either>` sets which
+/// way levels must move, `--allow-equal` permits equal neighbours, and
+/// `--tolerance <k>` sets how many levels the dampener may remove.
+/// `--unsafe` lists every unsafe report with the reason why instead.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    _out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    let mut policy = SafetyPolicy::default();
+    let mut tolerance = 1;
+    let mut list_unsafe = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let mut value = || {
+            options
+                .next()
+                .ok_or_else(|| anyhow!("{option} needs a value"))
+        };
+        match option.as_str() {
+            "--min-step" => policy.min_step = value()?.parse()?,
+            "--max-step" => policy.max_step = value()?.parse()?,
+            "--trend" => policy.trend = value()?.parse()?,
+            "--allow-equal" => policy.allow_equal = true,
+            "--tolerance" => tolerance = value()?.parse()?,
+            "--unsafe" => list_unsafe = true,
+            other => return Err(anyhow!("Unknown option for day 2: {other}")),
+        }
+    }
+
+    let reports = parse_reports(input)?;
+    if list_unsafe {
+        return Ok(list_unsafe_reports(&reports, &policy, tolerance));
+    }
+    let safe = part_one(&reports, &policy);
+    let dampened = reports
+        .iter()
+        .filter(|report| dampened_report_is_safe(report, tolerance, &policy).is_some())
+        .count();
+    Ok(format!(
+        "{policy:?}\nSafe reports: {safe}\nSafe removing up to {tolerance} levels: {dampened}"
+    ))
+}
+
+fn list_unsafe_reports(reports: &[Vec<i32>], policy: &SafetyPolicy, tolerance: usize) -> String {
+    let mut lines = Vec::new();
+    for (idx, report) in reports.iter().enumerate() {
+        let diagnosis = diagnose_report(report, policy, tolerance);
+        let Some(violation) = diagnosis.violation else {
+            continue;
+        };
+        let dampener = match diagnosis.removed {
+            Some(removed) => format!("safe without levels {removed:?}"),
+            None => format!("unsafe even removing {tolerance} levels"),
+        };
+        lines.push(format!(
+            "Report {}: {} -- {violation}; {dampener}",
+            idx + 1,
+            report.iter().join(" ")
+        ));
+    }
+    lines.join("\n")
+}
+
+fn part_one(reports: &[Vec<i32>], policy: &SafetyPolicy) -> usize {
     reports
         .iter()
-        .filter(|report| report_is_safe(report))
+        .filter(|report| report_is_safe(report, policy))
         .count()
 }
 
-fn part_two(reports: &[Vec<i32>]) -> usize {
+fn part_two(reports: &[Vec<i32>], policy: &SafetyPolicy) -> usize {
     reports
         .iter()
-        .filter(|report| dampened_report_is_safe(report))
+        .filter(|report| dampened_report_is_safe(report, 1, policy).is_some())
         .count()
 }
 
+/// Which way the levels in a report must move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Trend {
+    Increasing,
+    Decreasing,
+    Either,
+}
+
+impl Trend {
+    /// The signs a report's steps may have, 1 for increasing and -1 for
+    /// decreasing. Every step in a report must share the same one.
+    fn directions(&self) -> &'static [i32] {
+        match self {
+            Trend::Increasing => &[1],
+            Trend::Decreasing => &[-1],
+            Trend::Either => &[1, -1],
+        }
+    }
+}
+
+impl FromStr for Trend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "increasing" => Ok(Trend::Increasing),
+            "decreasing" => Ok(Trend::Decreasing),
+            "either" => Ok(Trend::Either),
+            other => Err(anyhow!("Unknown trend: {other:?}")),
+        }
+    }
+}
+
+/// What makes a report safe. The default is the puzzle's rule: levels all
+/// increase or all decrease, by between 1 and 3 at each step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SafetyPolicy {
+    min_step: i32,
+    max_step: i32,
+    trend: Trend,
+    /// Whether neighbouring levels may be equal, regardless of `min_step`.
+    allow_equal: bool,
+}
+
+impl Default for SafetyPolicy {
+    fn default() -> Self {
+        Self {
+            min_step: 1,
+            max_step: 3,
+            trend: Trend::Either,
+            allow_equal: false,
+        }
+    }
+}
+
+impl SafetyPolicy {
+    /// Whether moving from `earlier` to `later` is safe for a report
+    /// heading in `direction`.
+    fn step_is_safe(&self, earlier: i32, later: i32, direction: i32) -> bool {
+        let step = (later - earlier) * direction;
+        if step == 0 {
+            self.allow_equal
+        } else {
+            (self.min_step..=self.max_step).contains(&step)
+        }
+    }
+}
+
 fn parse_reports(input: &str) -> Result<Vec<Vec<i32>>, ParseIntError> {
     fn line_to_nums(line: &str) -> Result<Vec<i32>, ParseIntError> {
         line.split_whitespace().map(|n| n.parse()).collect()
@@ -37,39 +177,154 @@ fn parse_reports(input: &str) -> Result<Vec<Vec<i32>>, ParseIntError> {
     input.lines().map(line_to_nums).collect()
 }
 
-fn report_is_safe(report: &[i32]) -> bool {
-    fn signs_match((a, b): (&i32, &i32)) -> bool {
-        a.signum() == b.signum()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ViolationKind {
+    StepTooBig,
+    StepTooSmall,
+    ZeroStep,
+    /// The report turns around after earlier steps set its direction.
+    DirectionChange,
+    /// The report moves against the policy's required [`Trend`].
+    WrongDirection,
+}
+
+/// The first unsafe step in a report, at `index` being the level the step
+/// moves to (so it is never 0).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Violation {
+    index: usize,
+    kind: ViolationKind,
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            ViolationKind::StepTooBig => "step too big",
+            ViolationKind::StepTooSmall => "step too small",
+            ViolationKind::ZeroStep => "zero step",
+            ViolationKind::DirectionChange => "direction change",
+            ViolationKind::WrongDirection => "wrong direction",
+        };
+        write!(f, "{kind} at index {}", self.index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Diagnosis {
+    /// Why the report is unsafe as it is, if it is.
+    violation: Option<Violation>,
+    /// The levels the dampener removed to make it safe, empty if it's safe
+    /// already and `None` if the dampener can't help.
+    removed: Option<Vec<usize>>,
+}
+
+fn first_violation(report: &[i32], policy: &SafetyPolicy) -> Option<Violation> {
+    let mut direction = match policy.trend {
+        Trend::Increasing => Some(1),
+        Trend::Decreasing => Some(-1),
+        Trend::Either => None,
+    };
+    for (index, (earlier, later)) in report.iter().tuple_windows().enumerate() {
+        let index = index + 1;
+        let step = later - earlier;
+        let kind = if step == 0 {
+            if policy.allow_equal {
+                continue;
+            }
+            ViolationKind::ZeroStep
+        } else if direction.is_some_and(|d| d != step.signum()) {
+            match policy.trend {
+                Trend::Either => ViolationKind::DirectionChange,
+                _ => ViolationKind::WrongDirection,
+            }
+        } else if step.abs() > policy.max_step {
+            ViolationKind::StepTooBig
+        } else if step.abs() < policy.min_step {
+            ViolationKind::StepTooSmall
+        } else {
+            direction = Some(step.signum());
+            continue;
+        };
+        return Some(Violation { index, kind });
     }
+    None
+}
 
-    fn diff_in_range(level: &i32) -> bool {
-        matches!(level.abs(), 1..=3)
+fn diagnose_report(report: &[i32], policy: &SafetyPolicy, tolerance: usize) -> Diagnosis {
+    Diagnosis {
+        violation: first_violation(report, policy),
+        removed: dampened_report_is_safe(report, tolerance, policy),
     }
+}
+
+fn report_is_safe(report: &[i32], policy: &SafetyPolicy) -> bool {
+    policy.trend.directions().iter().any(|&direction| {
+        report
+            .iter()
+            .tuple_windows()
+            .all(|(&earlier, &later)| policy.step_is_safe(earlier, later, direction))
+    })
+}
 
-    let diffs: Vec<i32> = report
+/// Check whether a report can be made safe by removing at most `k` levels,
+/// returning the indices of the fewest levels that need removing if so.
+fn dampened_report_is_safe(report: &[i32], k: usize, policy: &SafetyPolicy) -> Option<Vec<usize>> {
+    policy
+        .trend
+        .directions()
         .iter()
-        .tuple_windows()
-        .map(|(first, second)| first - second)
-        .collect();
-    let all_diffs_in_range = diffs.iter().all(diff_in_range);
-    let all_diffs_have_same_sign = diffs.iter().tuple_windows().all(signs_match);
-
-    all_diffs_in_range && all_diffs_have_same_sign
-}
-
-fn dampened_report_is_safe(report: &[i32]) -> bool {
-    if report_is_safe(report) {
-        true
-    } else {
-        for idx in 0..report.len() {
-            let mut new_report = report.to_vec();
-            new_report.remove(idx);
-            if report_is_safe(&new_report) {
-                return true;
+        .filter_map(|&direction| fewest_removals(report, k, policy, direction))
+        .min_by_key(Vec::len)
+}
+
+/// Find the longest subsequence of levels that all step safely in
+/// `direction` (1 for increasing, -1 for decreasing) under `policy`; the
+/// levels left out are the ones to remove.
+///
+/// Each level's best chain extends the best chain ending at one of the
+/// `k + 1` levels before it, as skipping any more would exceed `k` removals,
+/// so this takes O(nk) time.
+fn fewest_removals(
+    report: &[i32],
+    k: usize,
+    policy: &SafetyPolicy,
+    direction: i32,
+) -> Option<Vec<usize>> {
+    let n = report.len();
+    // Length of the longest safe chain ending at each level, with 0 for
+    // levels that can't be reached without removing more than `k` before them.
+    let mut length = vec![0_usize; n];
+    let mut previous: Vec<Option<usize>> = vec![None; n];
+    for idx in 0..n {
+        if idx <= k {
+            length[idx] = 1;
+        }
+        for earlier in idx.saturating_sub(k + 1)..idx {
+            let safe = policy.step_is_safe(report[earlier], report[idx], direction);
+            if length[earlier] > 0 && safe && length[earlier] + 1 > length[idx] {
+                length[idx] = length[earlier] + 1;
+                previous[idx] = Some(earlier);
             }
         }
-        false
     }
+
+    let Some(last) = (0..n)
+        .filter(|&idx| n - 1 - idx <= k)
+        .max_by_key(|&idx| length[idx])
+    else {
+        // An empty report is trivially safe.
+        return Some(Vec::new());
+    };
+    if n - length[last] > k {
+        return None;
+    }
+    let mut kept = vec![false; n];
+    let mut cursor = Some(last);
+    while let Some(idx) = cursor {
+        kept[idx] = true;
+        cursor = previous[idx];
+    }
+    Some((0..n).filter(|&idx| !kept[idx]).collect())
 }
 
 #[cfg(test)]
@@ -78,7 +333,9 @@ mod test {
 
     use rstest::rstest;
 
-    use crate::days::day02::report_is_safe;
+    use crate::days::day02::{
+        report_is_safe, Diagnosis, SafetyPolicy, Trend, Violation, ViolationKind,
+    };
 
     const SAMPLE_INPUT: &str = "\
 7 6 4 2 1
@@ -112,7 +369,7 @@ mod test {
     #[case(&[1, 3, 6, 7, 9])]
     #[trace]
     pub fn expected_safe_p1(#[case] report: &[i32]) {
-        assert!(super::report_is_safe(report));
+        assert!(super::report_is_safe(report, &SafetyPolicy::default()));
     }
 
     #[rstest]
@@ -122,7 +379,7 @@ mod test {
     #[case(&[8, 6, 4, 4, 1])]
     #[trace]
     pub fn expected_unsafe_p1(#[case] report: &[i32]) {
-        assert!(!super::report_is_safe(report));
+        assert!(!super::report_is_safe(report, &SafetyPolicy::default()));
     }
 
     #[rstest]
@@ -132,7 +389,7 @@ mod test {
     #[case(&[8, 6, 4, 4, 1])]
     #[trace]
     pub fn expected_safe_p2(#[case] report: &[i32]) {
-        assert!(super::dampened_report_is_safe(report));
+        assert!(super::dampened_report_is_safe(report, 1, &SafetyPolicy::default()).is_some());
     }
 
     #[rstest]
@@ -140,22 +397,133 @@ mod test {
     #[case(&[9, 7, 6, 2, 1])]
     #[trace]
     pub fn expected_unsafe_p2(#[case] report: &[i32]) {
-        assert!(!super::dampened_report_is_safe(report));
+        assert!(super::dampened_report_is_safe(report, 1, &SafetyPolicy::default()).is_none());
+    }
+
+    #[rstest]
+    #[case(&[7, 6, 4, 2, 1], 0, Some(vec![]))]
+    #[case(&[1, 3, 2, 4, 5], 0, None)]
+    #[case(&[1, 3, 2, 4, 5], 1, Some(vec![2]))]
+    #[case(&[8, 6, 4, 4, 1], 1, Some(vec![3]))]
+    #[case(&[1, 2, 7, 8, 9], 1, None)]
+    #[case(&[1, 2, 7, 8, 9], 2, Some(vec![0, 1]))]
+    #[case(&[9, 7, 6, 2, 1], 2, Some(vec![3, 4]))]
+    #[case(&[50, 1, 2, 3, 40, 4], 2, Some(vec![0, 4]))]
+    #[case(&[], 0, Some(vec![]))]
+    #[trace]
+    pub fn dampener_removes_fewest_levels(
+        #[case] report: &[i32],
+        #[case] k: usize,
+        #[case] expected: Option<Vec<usize>>,
+    ) {
+        let removed = super::dampened_report_is_safe(report, k, &SafetyPolicy::default());
+        assert_eq!(removed, expected);
     }
 
     #[test]
     pub fn solve_sample_part_one() {
-        assert_eq!(super::part_one(&sample_reports()), 2);
+        assert_eq!(
+            super::part_one(&sample_reports(), &SafetyPolicy::default()),
+            2
+        );
     }
 
     #[test]
     pub fn solve_sample_part_two() {
-        assert_eq!(super::part_two(&sample_reports()), 4);
+        assert_eq!(
+            super::part_two(&sample_reports(), &SafetyPolicy::default()),
+            4
+        );
     }
 
     #[test]
     pub fn ensure_first_two_levels_are_checked() {
         let report = &[14, 10, 9, 6, 4, 3, 2, 1];
-        assert!(!report_is_safe(report));
+        assert!(!report_is_safe(report, &SafetyPolicy::default()));
+    }
+
+    #[rstest]
+    #[case(&[1, 2, 3, 4], Trend::Increasing, true)]
+    #[case(&[1, 2, 3, 4], Trend::Decreasing, false)]
+    #[case(&[4, 3, 2, 1], Trend::Decreasing, true)]
+    #[case(&[4, 3, 2, 1], Trend::Increasing, false)]
+    #[trace]
+    pub fn policy_trend(#[case] report: &[i32], #[case] trend: Trend, #[case] safe: bool) {
+        let policy = SafetyPolicy {
+            trend,
+            ..Default::default()
+        };
+        assert_eq!(report_is_safe(report, &policy), safe);
+    }
+
+    #[rstest]
+    #[case(&[7, 6, 4, 2, 1], None)]
+    #[case(&[1, 2, 7, 8, 9], Some((2, ViolationKind::StepTooBig)))]
+    #[case(&[9, 7, 6, 2, 1], Some((3, ViolationKind::StepTooBig)))]
+    #[case(&[1, 3, 2, 4, 5], Some((2, ViolationKind::DirectionChange)))]
+    #[case(&[8, 6, 4, 4, 1], Some((3, ViolationKind::ZeroStep)))]
+    #[case(&[14, 10, 9, 6], Some((1, ViolationKind::StepTooBig)))]
+    #[trace]
+    pub fn find_first_violation(
+        #[case] report: &[i32],
+        #[case] expected: Option<(usize, ViolationKind)>,
+    ) {
+        let violation = super::first_violation(report, &SafetyPolicy::default());
+        assert_eq!(violation.map(|v| (v.index, v.kind)), expected);
+        // The diagnosis agrees with the plain safety check.
+        assert_eq!(
+            violation.is_none(),
+            report_is_safe(report, &SafetyPolicy::default())
+        );
+    }
+
+    #[test]
+    pub fn first_violation_under_policy() {
+        let increasing = SafetyPolicy {
+            trend: Trend::Increasing,
+            min_step: 2,
+            ..Default::default()
+        };
+        let kind = |report: &[i32]| super::first_violation(report, &increasing).map(|v| v.kind);
+        assert_eq!(kind(&[5, 3, 1]), Some(ViolationKind::WrongDirection));
+        assert_eq!(kind(&[1, 3, 4]), Some(ViolationKind::StepTooSmall));
+        assert_eq!(kind(&[1, 3, 5]), None);
+    }
+
+    #[test]
+    pub fn diagnose_dampened_report() {
+        let diagnosis = super::diagnose_report(&[1, 3, 2, 4, 5], &SafetyPolicy::default(), 1);
+        assert_eq!(
+            diagnosis,
+            Diagnosis {
+                violation: Some(Violation {
+                    index: 2,
+                    kind: ViolationKind::DirectionChange
+                }),
+                removed: Some(vec![2]),
+            }
+        );
+        let diagnosis = super::diagnose_report(&[1, 2, 7, 8, 9], &SafetyPolicy::default(), 1);
+        assert_eq!(diagnosis.removed, None);
+    }
+
+    #[test]
+    pub fn policy_steps_and_equal_neighbours() {
+        let wide = SafetyPolicy {
+            min_step: 2,
+            max_step: 5,
+            ..Default::default()
+        };
+        assert!(report_is_safe(&[1, 6, 8, 13], &wide));
+        assert!(!report_is_safe(&[1, 2, 4], &wide));
+
+        let flat = SafetyPolicy {
+            allow_equal: true,
+            ..Default::default()
+        };
+        assert!(report_is_safe(&[8, 6, 4, 4, 1], &flat));
+        assert!(!report_is_safe(&[1, 2, 2, 1], &flat));
+        // Equal neighbours are allowed whichever way the report is heading.
+        assert!(report_is_safe(&[3, 3, 3], &flat));
     }
 }
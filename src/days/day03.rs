@@ -1,120 +1,779 @@
-use regex::Regex;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use itertools::Itertools;
+use num_bigint::BigInt;
 
 use crate::util::Answer;
 
 pub fn solve(input: &str) -> anyhow::Result<String> {
-    let p1 = part_one(input);
-    assert_eq!(p1, 178794710, "Part one answer isn't correct");
+    let p1 = part_one(input, Precision::I64)?;
+    assert_eq!(p1, Total::I64(178794710), "Part one answer isn't correct");
 
-    let p2 = part_two(input);
-    assert_eq!(p2, 76729637, "Part two answer isn't correct");
+    let p2 = part_two(input, Precision::I64)?;
+    assert_eq!(p2, Total::I64(76729637), "Part two answer isn't correct");
 
     Answer::first(3, p1).second(p2).report()
 }
 
-fn part_one(input: &str) -> i32 {
-    parse_instructions(input)
-        .into_iter()
-        .filter_map(|ins| match ins {
-            Instruction::Multiply(a, b) => Some(a * b),
-            _ => None,
+/// Options: `--precision <i32|i64|i128|big>` sets the integer type totals
+/// are accumulated in (overflowing it is an error), and `--trace` lists
+/// every instruction found in memory, its byte offset, and whether it was
+/// executed or ignored under part two's rules.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    _out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    let mut precision = Precision::default();
+    let mut show_trace = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--trace" => show_trace = true,
+            "--precision" => {
+                let value = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--precision needs a value: i32, i64, i128 or big"))?;
+                precision = value.parse()?;
+            }
+            other => return Err(anyhow!("Unknown option for day 3: {other}")),
+        }
+    }
+    if !show_trace {
+        let p1 = part_one(input, precision)?;
+        let p2 = part_two(input, precision)?;
+        return Answer::first(3, p1).second(p2).report();
+    }
+
+    let Run { machine, trace } = InstructionSet::conditional()
+        .with_precision(precision)
+        .trace(input.as_bytes())?;
+    let mut lines: Vec<String> = trace
+        .iter()
+        .map(|entry| {
+            let status = if entry.executed {
+                "executed"
+            } else {
+                "ignored"
+            };
+            format!(
+                "{:>6}  {:<12} {status}",
+                entry.instruction.offset,
+                entry.instruction.to_string()
+            )
         })
-        .sum()
-}
-
-fn part_two(input: &str) -> i32 {
-    use Instruction::{Do, Dont, Multiply};
-    use State::{Disabled, Enabled};
-    let (_, sum) = parse_instructions(input)
-        .into_iter()
-        .fold((Enabled, 0), |(state, sum), ins| match (state, ins) {
-            (Enabled, Multiply(a, b)) => (state, sum + a * b),
-            (Disabled, Multiply(_, _)) => (state, sum),
-            (_, Do) => (Enabled, sum),
-            (_, Dont) => (Disabled, sum),
-        });
-    sum
-}
-
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum Instruction {
-    Do,
-    Dont,
-    Multiply(i32, i32),
-}
-
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-enum State {
-    Enabled,
-    Disabled,
-}
-
-fn parse_instructions(input: &str) -> Vec<Instruction> {
-    let mul_regex = Regex::new(r"do\(\)|don't\(\)|mul\((\d+),(\d+)\)").unwrap();
-    let mut instructions = Vec::new();
-    for m in mul_regex.captures_iter(input) {
-        let match_text = m.get(0).unwrap().as_str();
-        if match_text.starts_with("don't") {
-            instructions.push(Instruction::Dont);
-        } else if match_text.starts_with("do") {
-            instructions.push(Instruction::Do);
-        } else if match_text.starts_with("mul") {
-            let first: i32 = m.get(1).unwrap().as_str().parse().unwrap();
-            let second: i32 = m.get(2).unwrap().as_str().parse().unwrap();
-            instructions.push(Instruction::Multiply(first, second));
+        .collect();
+    lines.push(format!(
+        "{} of {} instructions executed, total {}",
+        machine.executed,
+        trace.len(),
+        machine.total
+    ));
+    Ok(lines.join("\n"))
+}
+
+fn part_one(input: &str, precision: Precision) -> anyhow::Result<Total> {
+    let set = InstructionSet::multiply_only().with_precision(precision);
+    Ok(set.run(input.as_bytes())?.total)
+}
+
+fn part_two(input: &str, precision: Precision) -> anyhow::Result<Total> {
+    let set = InstructionSet::conditional().with_precision(precision);
+    Ok(set.run(input.as_bytes())?.total)
+}
+
+/// The integer type a [`Machine`]'s total is kept in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Precision {
+    I32,
+    #[default]
+    I64,
+    I128,
+    /// Arbitrary precision, which never overflows.
+    Big,
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "i32" => Ok(Precision::I32),
+            "i64" => Ok(Precision::I64),
+            "i128" => Ok(Precision::I128),
+            "big" => Ok(Precision::Big),
+            other => Err(anyhow!("Unknown precision: {other:?}")),
+        }
+    }
+}
+
+/// A running total at some [`Precision`]. Arithmetic on it is checked, and
+/// overflowing is an error rather than wrapping around or panicking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Total {
+    I32(i32),
+    I64(i64),
+    I128(i128),
+    Big(BigInt),
+}
+
+impl Total {
+    fn zero(precision: Precision) -> Self {
+        match precision {
+            Precision::I32 => Total::I32(0),
+            Precision::I64 => Total::I64(0),
+            Precision::I128 => Total::I128(0),
+            Precision::Big => Total::Big(BigInt::ZERO),
+        }
+    }
+
+    fn add(&mut self, rhs: i64) -> anyhow::Result<()> {
+        let overflowed = match self {
+            Total::I32(n) => i32::try_from(rhs)
+                .ok()
+                .and_then(|rhs| n.checked_add(rhs))
+                .map(|sum| *n = sum)
+                .is_none(),
+            Total::I64(n) => n.checked_add(rhs).map(|sum| *n = sum).is_none(),
+            Total::I128(n) => n.checked_add(rhs.into()).map(|sum| *n = sum).is_none(),
+            Total::Big(n) => {
+                *n += rhs;
+                false
+            }
+        };
+        self.check(overflowed, "+", rhs)
+    }
+
+    fn check(&self, overflowed: bool, op: &str, rhs: i64) -> anyhow::Result<()> {
+        if overflowed {
+            Err(anyhow!(
+                "Total overflowed: {self} {op} {rhs} doesn't fit in {:?}",
+                self.precision()
+            ))
         } else {
-            unreachable!("Only do, don't and mul are matched by the regex.")
+            Ok(())
+        }
+    }
+
+    fn precision(&self) -> Precision {
+        match self {
+            Total::I32(_) => Precision::I32,
+            Total::I64(_) => Precision::I64,
+            Total::I128(_) => Precision::I128,
+            Total::Big(_) => Precision::Big,
+        }
+    }
+}
+
+impl std::fmt::Display for Total {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Total::I32(n) => n.fmt(f),
+            Total::I64(n) => n.fmt(f),
+            Total::I128(n) => n.fmt(f),
+            Total::Big(n) => n.fmt(f),
+        }
+    }
+}
+
+/// The kinds of argument an opcode can take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArgKind {
+    /// One to three decimal digits.
+    Number,
+    /// One to sixteen ASCII letters or underscores. None of the puzzle's
+    /// opcodes take one, but custom opcodes may.
+    #[allow(dead_code)]
+    Word,
+}
+
+impl ArgKind {
+    fn accepts(&self, byte: u8) -> bool {
+        match self {
+            ArgKind::Number => byte.is_ascii_digit(),
+            ArgKind::Word => byte.is_ascii_alphabetic() || byte == b'_',
+        }
+    }
+
+    /// The longest argument of this kind, in bytes. Longer runs of accepted
+    /// bytes aren't an argument at all, so `mul(1234,5)` is just noise.
+    fn max_len(&self) -> usize {
+        match self {
+            ArgKind::Number => 3,
+            ArgKind::Word => 16,
+        }
+    }
+
+    fn parse(&self, text: &str) -> Option<Arg> {
+        match self {
+            ArgKind::Number => text.parse().ok().map(Arg::Number),
+            ArgKind::Word => Some(Arg::Word(text.to_owned())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Arg {
+    Number(i32),
+    Word(String),
+}
+
+impl std::fmt::Display for Arg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arg::Number(n) => write!(f, "{n}"),
+            Arg::Word(w) => write!(f, "{w}"),
+        }
+    }
+}
+
+/// Everything an instruction can read or change as it runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Machine {
+    enabled: bool,
+    total: Total,
+    /// How many instructions have been executed (rather than ignored).
+    executed: usize,
+}
+
+impl Machine {
+    fn new(precision: Precision) -> Self {
+        Self {
+            enabled: true,
+            total: Total::zero(precision),
+            executed: 0,
+        }
+    }
+}
+
+/// An opcode's behaviour. Arguments arrive already checked against the
+/// opcode's [`ArgKind`]s.
+type Execute = fn(&mut Machine, &[Arg]) -> anyhow::Result<()>;
+
+#[derive(Clone, Copy)]
+struct Opcode {
+    name: &'static str,
+    args: &'static [ArgKind],
+    /// Whether the opcode runs even while the machine is disabled, as
+    /// `do()` has to for the machine to ever be re-enabled.
+    control: bool,
+    execute: Execute,
+}
+
+const MUL: Opcode = Opcode {
+    name: "mul",
+    args: &[ArgKind::Number, ArgKind::Number],
+    control: false,
+    execute: |machine, args| {
+        let [Arg::Number(a), Arg::Number(b)] = args else {
+            unreachable!("mul takes two numbers.")
+        };
+        // Operands are at most three digits, so the product itself is safe.
+        machine.total.add(i64::from(*a) * i64::from(*b))
+    },
+};
+
+const DO: Opcode = Opcode {
+    name: "do",
+    args: &[],
+    control: true,
+    execute: |machine, _| {
+        machine.enabled = true;
+        Ok(())
+    },
+};
+
+const DONT: Opcode = Opcode {
+    name: "don't",
+    args: &[],
+    control: true,
+    execute: |machine, _| {
+        machine.enabled = false;
+        Ok(())
+    },
+};
+
+/// An instruction found in memory, `offset` bytes from the start.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Instruction {
+    offset: usize,
+    opcode: &'static str,
+    args: Vec<Arg>,
+}
+
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}({})", self.opcode, self.args.iter().join(","))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TraceEntry {
+    instruction: Instruction,
+    executed: bool,
+}
+
+struct Run {
+    machine: Machine,
+    trace: Vec<TraceEntry>,
+}
+
+/// The opcodes an interpreter recognises. Anything else in memory is noise.
+#[derive(Clone, Default)]
+struct InstructionSet {
+    opcodes: Vec<Opcode>,
+    precision: Precision,
+}
+
+impl InstructionSet {
+    fn with_precision(self, precision: Precision) -> Self {
+        Self { precision, ..self }
+    }
+
+    fn register(mut self, opcode: Opcode) -> Self {
+        self.opcodes.push(opcode);
+        self
+    }
+
+    /// Part one's instruction set.
+    fn multiply_only() -> Self {
+        Self::default().register(MUL)
+    }
+
+    /// Part two's instruction set.
+    fn conditional() -> Self {
+        Self::multiply_only().register(DO).register(DONT)
+    }
+
+    fn opcode(&self, name: &str) -> &Opcode {
+        self.opcodes
+            .iter()
+            .find(|op| op.name == name)
+            .expect("Instructions only come from registered opcodes.")
+    }
+
+    /// The length in bytes of the longest instruction in the set, which is
+    /// as much of memory as the scanner ever needs to hold at once.
+    fn max_instruction_len(&self) -> usize {
+        self.opcodes
+            .iter()
+            .map(|op| {
+                let args: usize = op.args.iter().map(ArgKind::max_len).sum();
+                let commas = op.args.len().saturating_sub(1);
+                op.name.len() + args + commas + 2
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Scan corrupted memory for well-formed instructions as it is read.
+    fn scan<R: BufRead>(&self, reader: R) -> Scanner<'_, R> {
+        Scanner {
+            set: self,
+            reader,
+            window: Vec::with_capacity(self.max_instruction_len()),
+            offset: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Try each opcode at the start of `rest`, which is `offset` bytes into
+    /// memory, returning the instruction and its length in bytes for the
+    /// first that matches.
+    fn instruction_at(&self, rest: &[u8], offset: usize) -> Option<(Instruction, usize)> {
+        self.opcodes.iter().find_map(|op| {
+            let name = op.name.as_bytes();
+            if !rest.starts_with(name) || rest.get(name.len()) != Some(&b'(') {
+                return None;
+            }
+            let mut pos = name.len() + 1;
+            let mut args = Vec::with_capacity(op.args.len());
+            for (idx, kind) in op.args.iter().enumerate() {
+                if idx > 0 {
+                    if rest.get(pos) != Some(&b',') {
+                        return None;
+                    }
+                    pos += 1;
+                }
+                let len = rest[pos..].iter().take_while(|&&b| kind.accepts(b)).count();
+                if len == 0 || len > kind.max_len() {
+                    return None;
+                }
+                let text = std::str::from_utf8(&rest[pos..pos + len]).ok()?;
+                args.push(kind.parse(text)?);
+                pos += len;
+            }
+            if rest.get(pos) != Some(&b')') {
+                return None;
+            }
+            let instruction = Instruction {
+                offset,
+                opcode: op.name,
+                args,
+            };
+            Some((instruction, pos + 1))
+        })
+    }
+
+    /// Execute the instructions in memory as it is read, holding no more
+    /// than one instruction's worth of it at once.
+    fn run<R: BufRead>(&self, reader: R) -> anyhow::Result<Machine> {
+        self.execute(reader, |_| {})
+    }
+
+    /// As [`InstructionSet::run`], but also keep a trace of every
+    /// instruction found, which grows with the memory.
+    fn trace<R: BufRead>(&self, reader: R) -> anyhow::Result<Run> {
+        let mut trace = Vec::new();
+        let machine = self.execute(reader, |entry| trace.push(entry))?;
+        Ok(Run { machine, trace })
+    }
+
+    fn execute<R: BufRead>(
+        &self,
+        reader: R,
+        mut record: impl FnMut(TraceEntry),
+    ) -> anyhow::Result<Machine> {
+        let mut machine = Machine::new(self.precision);
+        for instruction in self.scan(reader) {
+            let instruction = instruction?;
+            let opcode = self.opcode(instruction.opcode);
+            let executed = machine.enabled || opcode.control;
+            if executed {
+                (opcode.execute)(&mut machine, &instruction.args).with_context(|| {
+                    format!("Executing {instruction} at offset {}", instruction.offset)
+                })?;
+                machine.executed += 1;
+            }
+            record(TraceEntry {
+                instruction,
+                executed,
+            });
+        }
+        Ok(machine)
+    }
+}
+
+/// Scans memory from a reader for instructions, holding no more than the
+/// longest possible instruction in memory at once, so instructions split
+/// across the reader's buffer boundaries are still found.
+///
+/// Like a regex, matches don't overlap: scanning resumes after each one.
+struct Scanner<'s, R> {
+    set: &'s InstructionSet,
+    reader: R,
+    /// Bytes from `offset` onwards that have been read but not yet scanned.
+    window: Vec<u8>,
+    offset: usize,
+    exhausted: bool,
+}
+
+impl<R: BufRead> Scanner<'_, R> {
+    /// Top up the window until it can hold any instruction, or the reader
+    /// runs dry.
+    fn fill_window(&mut self) -> std::io::Result<()> {
+        let wanted = self.set.max_instruction_len();
+        while self.window.len() < wanted && !self.exhausted {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                self.exhausted = true;
+                break;
+            }
+            let take = buffer.len().min(wanted - self.window.len());
+            self.window.extend_from_slice(&buffer[..take]);
+            self.reader.consume(take);
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Iterator for Scanner<'_, R> {
+    type Item = std::io::Result<Instruction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Err(e) = self.fill_window() {
+                return Some(Err(e));
+            }
+            if self.window.is_empty() {
+                return None;
+            }
+            let found = self.set.instruction_at(&self.window, self.offset);
+            let skip = found.as_ref().map_or(1, |(_, len)| *len);
+            self.window.drain(..skip);
+            self.offset += skip;
+            if let Some((instruction, _)) = found {
+                return Some(Ok(instruction));
+            }
         }
     }
-    instructions
 }
 
 #[cfg(test)]
 mod test {
-    use super::Instruction;
+    use std::io::BufReader;
+
+    use regex::Regex;
+
+    use num_bigint::BigInt;
+
+    use super::{Arg, ArgKind, Instruction, InstructionSet, Opcode, Precision, Total, TraceEntry};
 
     const SAMPLE_INPUT_P1: &str =
         "xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
-    const SAMPLE_INSTRUCTIONS: &[Instruction] = &[
-        Instruction::Multiply(2, 4),
-        Instruction::Multiply(5, 5),
-        Instruction::Multiply(11, 8),
-        Instruction::Multiply(8, 5),
-    ];
     const SAMPLE_INPUT_P2: &str =
         "xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
-    const STRICT_SAMPLE_INSTRUCTIONS: &[Instruction] = &[
-        Instruction::Multiply(2, 4),
-        Instruction::Dont,
-        Instruction::Multiply(5, 5),
-        Instruction::Multiply(11, 8),
-        Instruction::Do,
-        Instruction::Multiply(8, 5),
-    ];
+
+    /// Every instruction in `memory`, in order.
+    fn tokenize(set: &InstructionSet, memory: &str) -> Vec<Instruction> {
+        set.scan(memory.as_bytes())
+            .collect::<std::io::Result<_>>()
+            .expect("Reading from a byte slice can't fail.")
+    }
+
+    fn mul(offset: usize, a: i32, b: i32) -> Instruction {
+        Instruction {
+            offset,
+            opcode: "mul",
+            args: vec![Arg::Number(a), Arg::Number(b)],
+        }
+    }
+
+    fn control(offset: usize, opcode: &'static str) -> Instruction {
+        Instruction {
+            offset,
+            opcode,
+            args: vec![],
+        }
+    }
 
     #[test]
     fn parse_sample_input_p1() {
         assert_eq!(
-            &super::parse_instructions(SAMPLE_INPUT_P1),
-            SAMPLE_INSTRUCTIONS
+            tokenize(&InstructionSet::multiply_only(), SAMPLE_INPUT_P1),
+            [mul(1, 2, 4), mul(29, 5, 5), mul(53, 11, 8), mul(62, 8, 5)]
         );
     }
 
     #[test]
-    fn solve_sample_p1() {
-        assert_eq!(super::part_one(SAMPLE_INPUT_P1), 161);
+    fn solve_sample_p1() -> anyhow::Result<()> {
+        assert_eq!(
+            super::part_one(SAMPLE_INPUT_P1, Precision::I64)?,
+            Total::I64(161)
+        );
+        Ok(())
     }
 
     #[test]
     fn parse_sample_input_p2() {
         assert_eq!(
-            &super::parse_instructions(SAMPLE_INPUT_P2),
-            STRICT_SAMPLE_INSTRUCTIONS
+            tokenize(&InstructionSet::conditional(), SAMPLE_INPUT_P2),
+            [
+                mul(1, 2, 4),
+                control(20, "don't"),
+                mul(28, 5, 5),
+                mul(48, 11, 8),
+                control(59, "do"),
+                mul(64, 8, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn solve_sample_p2() -> anyhow::Result<()> {
+        assert_eq!(
+            super::part_two(SAMPLE_INPUT_P2, Precision::I64)?,
+            Total::I64(48)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn trace_sample_p2() -> anyhow::Result<()> {
+        let run = InstructionSet::conditional().trace(SAMPLE_INPUT_P2.as_bytes())?;
+        let executed: Vec<(usize, bool)> = run
+            .trace
+            .iter()
+            .map(
+                |TraceEntry {
+                     instruction,
+                     executed,
+                 }| (instruction.offset, *executed),
+            )
+            .collect();
+        assert_eq!(
+            executed,
+            [
+                (1, true),
+                (20, true),
+                (28, false),
+                (48, false),
+                (59, true),
+                (64, true)
+            ]
+        );
+        assert_eq!(run.machine.executed, 4);
+        assert!(run.machine.enabled);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_overlong_operands() {
+        let set = InstructionSet::conditional();
+        assert!(tokenize(&set, "mul(1234,5)mul(5,1234)").is_empty());
+        assert_eq!(tokenize(&set, "mul(1234,5)mul(123,4)"), [mul(11, 123, 4)]);
+    }
+
+    #[test]
+    fn scan_across_buffer_boundaries() -> anyhow::Result<()> {
+        let set = InstructionSet::conditional();
+        let expected = tokenize(&set, SAMPLE_INPUT_P2);
+        for capacity in 1..16 {
+            let reader = BufReader::with_capacity(capacity, SAMPLE_INPUT_P2.as_bytes());
+            let run = set.trace(reader)?;
+            let found: Vec<Instruction> = run.trace.into_iter().map(|e| e.instruction).collect();
+            assert_eq!(found, expected, "Buffer capacity {capacity}");
+            assert_eq!(run.machine.total, Total::I64(48));
+            let reader = BufReader::with_capacity(capacity, SAMPLE_INPUT_P2.as_bytes());
+            assert_eq!(set.run(reader)?, run.machine);
+        }
+        Ok(())
+    }
+
+    /// A tiny xorshift generator, so fuzzed inputs are the same every run.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+    }
+
+    /// Corrupted memory built from fragments likely to form (or nearly form)
+    /// instructions.
+    fn fuzzed_memory(rng: &mut Xorshift, n_fragments: usize) -> String {
+        const FRAGMENTS: &[&str] = &[
+            "mul(", "mu", "l(", "do()", "don't()", "do", "n't", "()", "(", ")", ",", "1", "23",
+            "456", "7890", "x", " ", "mul(1,2)", "!@",
+        ];
+        (0..n_fragments)
+            .map(|_| FRAGMENTS[rng.next() % FRAGMENTS.len()])
+            .collect()
+    }
+
+    #[test]
+    fn scanner_matches_regex_on_fuzzed_input() -> std::io::Result<()> {
+        let regex = Regex::new(r"mul\((\d{1,3}),(\d{1,3})\)|do\(\)|don't\(\)").unwrap();
+        let set = InstructionSet::conditional();
+        let mut rng = Xorshift(0x2024_0003);
+        for _ in 0..500 {
+            let memory = fuzzed_memory(&mut rng, 60);
+            let expected: Vec<Instruction> = regex
+                .captures_iter(&memory)
+                .map(|c| {
+                    let m = c.get(0).unwrap();
+                    match (c.get(1), c.get(2)) {
+                        (Some(a), Some(b)) => mul(
+                            m.start(),
+                            a.as_str().parse().unwrap(),
+                            b.as_str().parse().unwrap(),
+                        ),
+                        _ if m.as_str() == "do()" => control(m.start(), "do"),
+                        _ => control(m.start(), "don't"),
+                    }
+                })
+                .collect();
+            let capacity = 1 + rng.next() % 8;
+            let reader = BufReader::with_capacity(capacity, memory.as_bytes());
+            let found = set.scan(reader).collect::<std::io::Result<Vec<_>>>()?;
+            assert_eq!(found, expected, "Memory {memory:?}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn register_new_opcodes() -> anyhow::Result<()> {
+        const SUB: Opcode = Opcode {
+            name: "sub",
+            args: &[ArgKind::Number, ArgKind::Number],
+            control: false,
+            execute: |machine, args| {
+                let [Arg::Number(a), Arg::Number(b)] = args else {
+                    unreachable!()
+                };
+                machine.total.add(i64::from(b - a))
+            },
+        };
+        const RESET: Opcode = Opcode {
+            name: "reset",
+            args: &[ArgKind::Word],
+            control: false,
+            execute: |machine, args| {
+                if args == [Arg::Word("total".to_owned())] {
+                    machine.total = Total::zero(Precision::I64);
+                }
+                Ok(())
+            },
+        };
+        let set = InstructionSet::conditional().register(SUB).register(RESET);
+        let run = set.trace("mul(3,4)sub(10,1)reset(x)reset(7)don't()sub(5,0)".as_bytes())?;
+        assert_eq!(run.machine.total, Total::I64(3));
+        assert_eq!(run.trace.len(), 5);
+        let run = set.trace("mul(3,4)reset(total)mul(1,2)".as_bytes())?;
+        assert_eq!(run.machine.total, Total::I64(2));
+        assert_eq!(run.trace[1].instruction.to_string(), "reset(total)");
+        Ok(())
+    }
+
+    #[test]
+    fn overflow_is_an_error() -> anyhow::Result<()> {
+        // 2,200 × 998,001 is more than i32::MAX.
+        let memory = "mul(999,999)".repeat(2200);
+        let err = super::part_one(&memory, Precision::I32).unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "Executing mul(999,999) at offset 25812: \
+             Total overflowed: 2146700151 + 998001 doesn't fit in I32"
         );
+
+        let expected = 2200 * 998_001;
+        assert_eq!(
+            super::part_one(&memory, Precision::I64)?,
+            Total::I64(expected)
+        );
+        assert_eq!(
+            super::part_one(&memory, Precision::I128)?,
+            Total::I128(expected.into())
+        );
+        assert_eq!(
+            super::part_one(&memory, Precision::Big)?,
+            Total::Big(BigInt::from(expected))
+        );
+        Ok(())
     }
 
     #[test]
-    fn solve_sample_p2() {
-        assert_eq!(super::part_two(SAMPLE_INPUT_P2), 48);
+    fn wide_totals_hold_more() -> anyhow::Result<()> {
+        let mut total = Total::zero(Precision::I64);
+        total.add(i64::MAX)?;
+        assert!(total.add(1).is_err());
+
+        let mut total = Total::zero(Precision::I128);
+        for _ in 0..4 {
+            total.add(i64::MAX)?;
+        }
+        assert_eq!(total, Total::I128(4 * i128::from(i64::MAX)));
+
+        let mut total = Total::zero(Precision::Big);
+        for _ in 0..4 {
+            total.add(i64::MAX)?;
+        }
+        assert_eq!(total, Total::Big(BigInt::from(i64::MAX) * 4));
+        Ok(())
     }
 }
@@ -1,6 +1,8 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 
+use anyhow::anyhow;
+
 use crate::util::Answer;
 
 pub fn solve(input: &str) -> anyhow::Result<String> {
@@ -15,12 +17,160 @@ pub fn solve(input: &str) -> anyhow::Result<String> {
     Answer::first(4, p1).second(p2).report()
 }
 
+/// Options: `--find <word>` (which can be repeated) lists every place the
+/// words appear, and `--once` counts palindromes once rather than once in
+/// each direction. `--pattern <pattern>` lists every place a 2D pattern
+/// such as `M.S/.A./M.S` matches instead. `--highlight <xmas|x-mas>` prints
+/// the grid with the letters outside that part's matches blanked out, and
+/// `--colour` adds colour where matches overlap.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    _out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    let grid: Grid = input.parse()?;
+    let mut words = Vec::new();
+    let mut palindromes = Palindromes::CountBoth;
+    let mut highlight = None;
+    let mut colour = Colour::Plain;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--highlight" => {
+                let mode = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--highlight needs xmas or x-mas"))?;
+                highlight = Some(mode.parse::<Highlight>()?);
+            }
+            "--colour" => colour = Colour::Ansi,
+            "--pattern" => {
+                let pattern: Pattern = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--pattern needs a pattern"))?
+                    .parse()?;
+                let matches = grid.find_pattern(&pattern);
+                let mut lines: Vec<String> = matches
+                    .iter()
+                    .map(|m| {
+                        let (row, col) = m.top_left;
+                        format!("({row}, {col}) {:?}", m.transform)
+                    })
+                    .collect();
+                lines.push(format!("{} matches", matches.len()));
+                return Ok(lines.join("\n"));
+            }
+            "--find" => {
+                let word = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--find needs a word"))?;
+                words.push(word.as_str());
+            }
+            "--once" => palindromes = Palindromes::CountOnce,
+            other => return Err(anyhow!("Unknown option for day 4: {other}")),
+        }
+    }
+    if let Some(highlight) = highlight {
+        return Ok(render(&grid, highlight, colour));
+    }
+    let matches = grid.find_words(&words, palindromes);
+    let mut lines: Vec<String> = matches
+        .iter()
+        .map(|m| {
+            let (row, col) = m.start;
+            format!("{} at ({row}, {col}) heading {:?}", m.word, m.direction)
+        })
+        .collect();
+    lines.push(format!("{} matches", matches.len()));
+    Ok(lines.join("\n"))
+}
+
 fn part_one(grid: &Grid) -> usize {
-    grid.count_xmas_positions()
+    grid.find_words(&["XMAS"], Palindromes::CountOnce).len()
 }
 
 fn part_two(grid: &Grid) -> usize {
-    grid.count_cross_mas_positions()
+    grid.find_pattern(&Pattern::x_mas()).len()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Direction {
+    UpLeft,
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+}
+
+impl Direction {
+    const ALL: [Direction; 8] = [
+        Direction::UpLeft,
+        Direction::Up,
+        Direction::UpRight,
+        Direction::Right,
+        Direction::DownRight,
+        Direction::Down,
+        Direction::DownLeft,
+        Direction::Left,
+    ];
+
+    /// The (row, column) change for one step in this direction.
+    fn delta(&self) -> (isize, isize) {
+        match self {
+            Direction::UpLeft => (-1, -1),
+            Direction::Up => (-1, 0),
+            Direction::UpRight => (-1, 1),
+            Direction::Right => (0, 1),
+            Direction::DownRight => (1, 1),
+            Direction::Down => (1, 0),
+            Direction::DownLeft => (1, -1),
+            Direction::Left => (0, -1),
+        }
+    }
+
+    /// The position `n` steps from `start`, if that isn't off the top or left.
+    fn step(&self, (row, col): (usize, usize), n: usize) -> Option<(usize, usize)> {
+        let (d_row, d_col) = self.delta();
+        let n = n as isize;
+        Some((
+            row.checked_add_signed(d_row * n)?,
+            col.checked_add_signed(d_col * n)?,
+        ))
+    }
+}
+
+/// Whether a palindrome, which reads the same in opposite directions,
+/// counts once or twice wherever it appears.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Palindromes {
+    CountBoth,
+    CountOnce,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct WordMatch<'w> {
+    word: &'w str,
+    start: (usize, usize),
+    direction: Direction,
+}
+
+impl WordMatch<'_> {
+    fn end(&self) -> (usize, usize) {
+        let length = self.word.chars().count();
+        self.direction
+            .step(self.start, length.saturating_sub(1))
+            .expect("A match's letters are all on the grid.")
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.word.chars().count()).map(|n| {
+            self.direction
+                .step(self.start, n)
+                .expect("A match's letters are all on the grid.")
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -40,104 +190,278 @@ impl Grid {
         self.at(pos).is_some_and(|&v| v == c)
     }
 
-    fn deltas_to_valid_positions<const N: usize>(
-        anchor: (usize, usize),
-        deltas: [(isize, isize); N],
-    ) -> Option<[(usize, usize); N]> {
-        fn checked_tuple_add(
-            (x_row, x_col): (usize, usize),
-            (d_row, d_col): (isize, isize),
-        ) -> Option<(usize, usize)> {
-            let row = x_row.checked_add_signed(d_row)?;
-            let col = x_col.checked_add_signed(d_col)?;
-            Some((row, col))
+    fn word_at(&self, letters: &[char], start: (usize, usize), direction: Direction) -> bool {
+        letters.iter().enumerate().all(|(n, &c)| {
+            direction
+                .step(start, n)
+                .is_some_and(|pos| self.check_char_at_position(c, &pos))
+        })
+    }
+
+    /// Every occurrence of `word` in any of the eight directions.
+    fn find_word<'w>(&self, word: &'w str) -> Vec<WordMatch<'w>> {
+        let letters: Vec<char> = word.chars().collect();
+        let Some(starts) = letters.first().and_then(|c| self.by_char.get(c)) else {
+            return Vec::new();
+        };
+        let letters = letters.as_slice();
+        starts
+            .iter()
+            .flat_map(|&start| {
+                Direction::ALL
+                    .into_iter()
+                    .filter(move |&direction| self.word_at(letters, start, direction))
+                    .map(move |direction| WordMatch {
+                        word,
+                        start,
+                        direction,
+                    })
+            })
+            .collect()
+    }
+
+    /// Every occurrence of each of `words`, sorted by word, start position
+    /// and direction.
+    ///
+    /// A palindrome found reading one way is also found reading back the
+    /// other way over the same letters. With [`Palindromes::CountOnce`] only
+    /// the first of those matches is kept.
+    fn find_words<'w>(&self, words: &[&'w str], palindromes: Palindromes) -> Vec<WordMatch<'w>> {
+        let mut matches: Vec<WordMatch> = words.iter().flat_map(|w| self.find_word(w)).collect();
+        matches.sort();
+        matches.dedup();
+        if palindromes == Palindromes::CountOnce {
+            let mut seen = BTreeSet::new();
+            matches.retain(|m| {
+                let (start, end) = (m.start, m.end());
+                seen.insert((m.word, start.min(end), start.max(end)))
+            });
         }
+        matches
+    }
+
+    /// Every place `pattern` matches, under each of its distinct rotations
+    /// and reflections, sorted by position. Where several transforms give
+    /// the same pattern (because it's symmetric) only the first is tried.
+    fn find_pattern(&self, pattern: &Pattern) -> Vec<PatternMatch> {
+        let mut variants: Vec<(Transform, Pattern)> = Vec::with_capacity(8);
+        for transform in Transform::all() {
+            let variant = transform.apply(pattern);
+            if !variants.iter().any(|(_, seen)| seen == &variant) {
+                variants.push((transform, variant));
+            }
+        }
+        let mut matches: Vec<PatternMatch> = self
+            .map
+            .keys()
+            .flat_map(|&top_left| {
+                variants
+                    .iter()
+                    .filter(move |(_, variant)| self.pattern_at(variant, top_left))
+                    .map(move |&(transform, _)| PatternMatch {
+                        top_left,
+                        transform,
+                    })
+            })
+            .collect();
+        matches.sort();
+        matches
+    }
+
+    fn pattern_at(&self, pattern: &Pattern, (top, left): (usize, usize)) -> bool {
+        pattern.rows.iter().enumerate().all(|(row_idx, row)| {
+            row.iter().enumerate().all(|(col_idx, cell)| match cell {
+                Some(c) => self.check_char_at_position(*c, &(top + row_idx, left + col_idx)),
+                None => self.at(&(top + row_idx, left + col_idx)).is_some(),
+            })
+        })
+    }
+}
+
+/// A small rectangle of letters to find in the grid, where `None` matches
+/// any letter. Written as rows separated by `/`, with `.` as the wildcard,
+/// so the X-shaped MAS is `M.S/.A./M.S`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    rows: Vec<Vec<Option<char>>>,
+}
+
+impl Pattern {
+    /// Part two's two MASes crossing in an X.
+    fn x_mas() -> Self {
+        "M.S/.A./M.S".parse().expect("The X-MAS pattern is valid.")
+    }
+
+    fn width(&self) -> usize {
+        self.rows.first().map_or(0, Vec::len)
+    }
 
-        let mapped = deltas.map(|d| checked_tuple_add(anchor, d));
-        mapped
+    /// Mirror the pattern left to right.
+    fn flip(&self) -> Self {
+        let rows = self
+            .rows
             .iter()
-            .all(Option::is_some)
-            .then(|| mapped.map(Option::unwrap))
+            .map(|row| row.iter().rev().copied().collect())
+            .collect();
+        Self { rows }
     }
 
-    fn count_xmas_from_x_position(&self, x_pos: (usize, usize)) -> usize {
-        // Sanity check.
-        assert!(
-            self.check_char_at_position('X', &x_pos),
-            "There is no X at {x_pos:?}"
-        );
-        // -mas deltas.
-        [
-            // Up and left.
-            [(-1, -1), (-2, -2), (-3, -3)],
-            // Up.
-            [(-1, 0), (-2, 0), (-3, 0)],
-            // Up and right.
-            [(-1, 1), (-2, 2), (-3, 3)],
-            // Right.
-            [(0, 1), (0, 2), (0, 3)],
-            // Down and right.
-            [(1, 1), (2, 2), (3, 3)],
-            // Down.
-            [(1, 0), (2, 0), (3, 0)],
-            // Down and left.
-            [(1, -1), (2, -2), (3, -3)],
-            // Left.
-            [(0, -1), (0, -2), (0, -3)],
-        ]
-        .into_iter()
-        .filter_map(|deltas| Self::deltas_to_valid_positions(x_pos, deltas))
-        .filter(|[m_pos, a_pos, s_pos]| {
-            self.check_char_at_position('M', m_pos)
-                && self.check_char_at_position('A', a_pos)
-                && self.check_char_at_position('S', s_pos)
+    /// Turn the pattern a quarter turn clockwise.
+    fn rotate(&self) -> Self {
+        let rows = (0..self.width())
+            .map(|col| self.rows.iter().rev().map(|row| row[col]).collect())
+            .collect();
+        Self { rows }
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rows: Vec<Vec<Option<char>>> = s
+            .split('/')
+            .map(|row| row.chars().map(|c| (c != '.').then_some(c)).collect())
+            .collect();
+        let width = rows[0].len();
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return Err(anyhow!("Pattern {s:?} isn't a filled-in rectangle."));
+        }
+        Ok(Self { rows })
+    }
+}
+
+/// One of the eight ways to rotate and reflect a pattern: an optional
+/// left-to-right flip followed by some clockwise quarter turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Transform {
+    flipped: bool,
+    quarter_turns: u8,
+}
+
+impl Transform {
+    fn all() -> impl Iterator<Item = Transform> {
+        [false, true].into_iter().flat_map(|flipped| {
+            (0..4).map(move |quarter_turns| Transform {
+                flipped,
+                quarter_turns,
+            })
         })
-        .count()
     }
 
-    fn is_cross_mas_at_position(&self, a_pos: (usize, usize)) -> bool {
-        // Sanity check.
-        assert!(
-            self.check_char_at_position('A', &a_pos),
-            "There is no A at {a_pos:?}"
-        );
-        let deltas: [(isize, isize); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
-        let Some([top_left, top_right, bottom_left, bottom_right]) =
-            Self::deltas_to_valid_positions(a_pos, deltas)
-        else {
-            return false;
+    fn apply(&self, pattern: &Pattern) -> Pattern {
+        let start = if self.flipped {
+            pattern.flip()
+        } else {
+            pattern.clone()
         };
+        (0..self.quarter_turns).fold(start, |p, _| p.rotate())
+    }
+}
 
-        let tl_br_mas = self.check_char_at_position('M', &top_left)
-            && self.check_char_at_position('S', &bottom_right);
-        let tl_br_sam = self.check_char_at_position('S', &top_left)
-            && self.check_char_at_position('M', &bottom_right);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct PatternMatch {
+    top_left: (usize, usize),
+    transform: Transform,
+}
 
-        let bl_tr_mas = self.check_char_at_position('M', &bottom_left)
-            && self.check_char_at_position('S', &top_right);
-        let bl_tr_sam = self.check_char_at_position('S', &bottom_left)
-            && self.check_char_at_position('M', &top_right);
+impl PatternMatch {
+    /// The grid positions of the letters (not the wildcards) of `pattern`
+    /// in this match.
+    fn positions(&self, pattern: &Pattern) -> Vec<(usize, usize)> {
+        let (top, left) = self.top_left;
+        let variant = self.transform.apply(pattern);
+        variant
+            .rows
+            .iter()
+            .enumerate()
+            .flat_map(|(row_idx, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, cell)| cell.is_some())
+                    .map(move |(col_idx, _)| (top + row_idx, left + col_idx))
+            })
+            .collect()
+    }
+}
 
-        (tl_br_mas || tl_br_sam) && (bl_tr_mas || bl_tr_sam)
+/// Which part's matches to pick out when rendering the grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Highlight {
+    Xmas,
+    CrossMas,
+}
+
+impl Highlight {
+    /// How many matches each position of the grid takes part in.
+    fn coverage(&self, grid: &Grid) -> BTreeMap<(usize, usize), usize> {
+        let positions: Vec<(usize, usize)> = match self {
+            Highlight::Xmas => grid
+                .find_words(&["XMAS"], Palindromes::CountOnce)
+                .iter()
+                .flat_map(WordMatch::positions)
+                .collect(),
+            Highlight::CrossMas => {
+                let x_mas = Pattern::x_mas();
+                grid.find_pattern(&x_mas)
+                    .iter()
+                    .flat_map(|m| m.positions(&x_mas))
+                    .collect()
+            }
+        };
+        let mut coverage = BTreeMap::new();
+        for position in positions {
+            *coverage.entry(position).or_default() += 1;
+        }
+        coverage
     }
+}
 
-    fn count_xmas_positions(&self) -> usize {
-        self.by_char
-            .get(&'X')
-            .unwrap()
-            .iter()
-            .map(|&x_pos| self.count_xmas_from_x_position(x_pos))
-            .sum()
+impl FromStr for Highlight {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "xmas" => Ok(Highlight::Xmas),
+            "x-mas" => Ok(Highlight::CrossMas),
+            other => Err(anyhow!(
+                "Unknown highlight mode {other:?}, expected xmas or x-mas."
+            )),
+        }
     }
+}
 
-    fn count_cross_mas_positions(&self) -> usize {
-        self.by_char
-            .get(&'A')
-            .unwrap()
-            .iter()
-            .filter(|&&a_pos| self.is_cross_mas_at_position(a_pos))
-            .count()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Colour {
+    Plain,
+    /// Letters shared by two matches are yellow, and by more are red.
+    Ansi,
+}
+
+/// Draw the grid as the puzzle does, with every letter that isn't part of
+/// a match replaced by `.`.
+fn render(grid: &Grid, highlight: Highlight, colour: Colour) -> String {
+    const YELLOW: &str = "\x1b[33m";
+    const RED: &str = "\x1b[31m";
+    const RESET: &str = "\x1b[0m";
+
+    let coverage = highlight.coverage(grid);
+    let mut out = String::new();
+    let mut current_row = 0;
+    for (&(row, col), &c) in &grid.map {
+        if row != current_row {
+            out.push('\n');
+            current_row = row;
+        }
+        let n_matches = coverage.get(&(row, col)).copied().unwrap_or(0);
+        match (n_matches, colour) {
+            (0, _) => out.push('.'),
+            (1, _) | (_, Colour::Plain) => out.push(c),
+            (2, Colour::Ansi) => out.push_str(&format!("{YELLOW}{c}{RESET}")),
+            (_, Colour::Ansi) => out.push_str(&format!("{RED}{c}{RESET}")),
+        }
     }
+    out
 }
 
 impl FromStr for Grid {
@@ -160,7 +484,10 @@ impl FromStr for Grid {
 
 #[cfg(test)]
 mod test {
-    use super::Grid;
+    use super::{
+        render, Colour, Direction, Grid, Highlight, Palindromes, Pattern, PatternMatch, Transform,
+        WordMatch,
+    };
 
     const SAMPLE_INPUT: &str = "\
 MMMSXXMASM
@@ -190,10 +517,152 @@ MXMXAXMASX
         Ok(())
     }
 
+    #[test]
+    fn find_word_positions_and_directions() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let matches = grid.find_words(&["XMAS"], Palindromes::CountBoth);
+        assert_eq!(matches.len(), 18);
+        assert!(matches.contains(&WordMatch {
+            word: "XMAS",
+            start: (0, 5),
+            direction: Direction::Right,
+        }));
+        assert!(matches.contains(&WordMatch {
+            word: "XMAS",
+            start: (9, 3),
+            direction: Direction::UpLeft,
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn find_several_words() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let both = grid.find_words(&["XMAS", "SAMX"], Palindromes::CountOnce);
+        assert_eq!(both.len(), 36);
+        // Every MAS is a SAM read backwards.
+        let mas = grid.find_words(&["MAS"], Palindromes::CountOnce);
+        let sam = grid.find_words(&["SAM"], Palindromes::CountOnce);
+        assert_eq!(mas.len(), sam.len());
+        assert!(grid
+            .find_words(&["XYZZY", ""], Palindromes::CountBoth)
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn count_palindromes_once() -> anyhow::Result<()> {
+        let grid: Grid = "ABA\nBXB\nABA\n".parse()?;
+        assert_eq!(grid.find_words(&["ABA"], Palindromes::CountBoth).len(), 8);
+        let once = grid.find_words(&["ABA"], Palindromes::CountOnce);
+        assert_eq!(once.len(), 4);
+        assert_eq!(once[0].start, (0, 0));
+        assert_eq!(grid.find_words(&["X"], Palindromes::CountBoth).len(), 8);
+        assert_eq!(grid.find_words(&["X"], Palindromes::CountOnce).len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_patterns() -> anyhow::Result<()> {
+        let pattern: Pattern = "M.S/.A.".parse()?;
+        assert_eq!(
+            pattern.rows,
+            [
+                vec![Some('M'), None, Some('S')],
+                vec![None, Some('A'), None]
+            ]
+        );
+        assert!("MS/A".parse::<Pattern>().is_err());
+        assert!("".parse::<Pattern>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn transform_patterns() -> anyhow::Result<()> {
+        let pattern: Pattern = "AB/CD/EF".parse()?;
+        let turned = Transform {
+            flipped: false,
+            quarter_turns: 1,
+        }
+        .apply(&pattern);
+        assert_eq!(turned, "ECA/FDB".parse()?);
+        let flipped = Transform {
+            flipped: true,
+            quarter_turns: 2,
+        }
+        .apply(&pattern);
+        assert_eq!(flipped, "EF/CD/AB".parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn find_x_mas_pattern() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let matches = grid.find_pattern(&Pattern::x_mas());
+        assert_eq!(matches.len(), 9);
+        // The X-MAS centred on (1, 2) has both Ms on the left.
+        assert_eq!(
+            matches[0],
+            PatternMatch {
+                top_left: (0, 1),
+                transform: Transform {
+                    flipped: false,
+                    quarter_turns: 0
+                },
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn solve_sample_part_two() -> anyhow::Result<()> {
         let grid: Grid = SAMPLE_INPUT.parse()?;
         assert_eq!(super::part_two(&grid), 9);
         Ok(())
     }
+
+    #[test]
+    fn highlight_xmas() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let expected = "\
+....XXMAS.
+.SAMXMS...
+...S..A...
+..A.A.MS.X
+XMASAMX.MM
+X.....XA.A
+S.S.S.S.SS
+.A.A.A.A.A
+..M.M.M.MM
+.X.X.XMASX";
+        assert_eq!(render(&grid, Highlight::Xmas, Colour::Plain), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn highlight_x_mas() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let expected = "\
+.M.S......
+..A..MSMS.
+.M.S.MAA..
+..A.ASMSM.
+.M.S.M....
+..........
+S.S.S.S.S.
+.A.A.A.A..
+M.M.M.M.M.
+..........";
+        assert_eq!(render(&grid, Highlight::CrossMas, Colour::Plain), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn colour_overlapping_matches() -> anyhow::Result<()> {
+        let grid: Grid = "XMASAMX\n".parse()?;
+        let coloured = render(&grid, Highlight::Xmas, Colour::Ansi);
+        // Only the S is shared by XMAS and its reverse.
+        assert_eq!(coloured, "XMA\x1b[33mS\x1b[0mAMX");
+        Ok(())
+    }
 }
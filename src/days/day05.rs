@@ -1,8 +1,10 @@
-use std::cmp::Ordering;
+use std::collections::VecDeque;
 
 use anyhow::anyhow;
+use itertools::Itertools;
+use nom::combinator::all_consuming;
 
-use parse::{Rules, Updates};
+use parse::Updates;
 
 use crate::util::Answer;
 
@@ -12,12 +14,69 @@ pub fn solve(input: &str) -> anyhow::Result<String> {
     let p1 = part_one(&rules, &updates);
     assert_eq!(p1, 6612, "Part one answer is not correct.");
 
-    let p2 = part_two(&rules, &updates);
+    let p2 = part_two(&rules, &updates)?;
     assert_eq!(p2, 4944, "Part two answer is not correct.");
 
     Answer::first(5, p1).second(p2).report()
 }
 
+/// Options: `--explain` lists every broken rule in each invalid update and
+/// the fewest moves that would put it in order. `--stream` feeds the input
+/// line by line through a [`RulesEngine`], logging each update's verdict and
+/// any earlier updates a new rule invalidates.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    _out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    match options {
+        [option] if option == "--explain" => explain_all(input),
+        [option] if option == "--stream" => stream(input),
+        _ => Err(anyhow!("Day 5 options: --explain, --stream")),
+    }
+}
+
+fn explain_all(input: &str) -> anyhow::Result<String> {
+    let (rules, updates) = parse_input(input)?;
+    let mut lines = Vec::new();
+    for (idx, update) in updates.iter().enumerate() {
+        if update_is_valid(&rules, update) {
+            continue;
+        }
+        lines.push(format!("Update {}: {}", idx + 1, update.iter().join(",")));
+        let Explanation { violations, moves } = explain_update(&rules, update);
+        lines.extend(violations.iter().map(|v| format!("  {v}")));
+        match moves {
+            Ok(moves) => lines.extend(moves.iter().map(|m| format!("  {m}"))),
+            Err(e) => lines.push(format!("  {e}")),
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+fn stream(input: &str) -> anyhow::Result<String> {
+    let mut engine = RulesEngine::new();
+    let mut log = Vec::new();
+    for line in input.lines().filter(|l| !l.is_empty()) {
+        if let Ok((_, (before, after))) = all_consuming(parse::rule)(line) {
+            let invalidated = engine.add_rule(before, after)?;
+            if !invalidated.is_empty() {
+                let numbers = invalidated.iter().map(|idx| idx + 1).join(", ");
+                log.push(format!("{before}|{after} invalidates updates {numbers}"));
+            }
+        } else {
+            let (_, update) = all_consuming(parse::update)(line).map_err(|e| e.to_owned())?;
+            let (idx, valid) = engine.add_update(update)?;
+            let verdict = if valid { "valid" } else { "invalid" };
+            log.push(format!("Update {} is {verdict}", idx + 1));
+        }
+    }
+    let n_updates = engine.updates.len();
+    let n_valid = (0..n_updates).filter(|&idx| engine.is_valid(idx)).count();
+    log.push(format!("{n_valid} of {n_updates} updates are valid"));
+    Ok(log.join("\n"))
+}
+
 fn part_one(rules: &Rules, updates: &Updates) -> u32 {
     updates
         .iter()
@@ -26,37 +85,262 @@ fn part_one(rules: &Rules, updates: &Updates) -> u32 {
         .sum()
 }
 
-fn part_two(rules: &Rules, updates: &Updates) -> u32 {
-    updates
+fn part_two(rules: &Rules, updates: &Updates) -> anyhow::Result<u32> {
+    let mut sum = 0;
+    for unsorted in updates.iter().filter(|&u| !update_is_valid(rules, u)) {
+        sum += middle_element(&sort_update(rules, unsorted)?);
+    }
+    Ok(sum)
+}
+
+fn sort_update(rules: &Rules, update: &[u32]) -> Result<Vec<u32>, OrderError> {
+    RulesGraph::new(rules).sort(update)
+}
+
+/// The ordering rules viewed as a directed graph, with an edge from each
+/// page to every page that must come after it.
+struct RulesGraph<'r> {
+    rules: &'r Rules,
+}
+
+impl<'r> RulesGraph<'r> {
+    fn new(rules: &'r Rules) -> Self {
+        Self { rules }
+    }
+
+    fn must_precede(&self, before: u32, after: u32) -> bool {
+        self.rules.must_precede(before, after)
+    }
+
+    /// Order the pages of an update using Kahn's algorithm on the subgraph
+    /// of rules that mention only pages in the update.
+    ///
+    /// Fails if those rules form a cycle, or if they allow more than one
+    /// order (in which case there's no single middle page to speak of).
+    fn sort(&self, update: &[u32]) -> Result<Vec<u32>, OrderError> {
+        let n = update.len();
+        let successors: Vec<Vec<usize>> = update
+            .iter()
+            .map(|&before| {
+                (0..n)
+                    .filter(|&idx| self.must_precede(before, update[idx]))
+                    .collect()
+            })
+            .collect();
+        let mut in_degree = vec![0_usize; n];
+        for &idx in successors.iter().flatten() {
+            in_degree[idx] += 1;
+        }
+
+        let mut ready: VecDeque<usize> = (0..n).filter(|&idx| in_degree[idx] == 0).collect();
+        let mut order = Vec::with_capacity(n);
+        let mut tied: Option<Vec<u32>> = None;
+        while let Some(idx) = ready.pop_front() {
+            if !ready.is_empty() && tied.is_none() {
+                // Any of these could come next, so the order isn't unique.
+                let pages = std::iter::once(idx).chain(ready.iter().copied());
+                tied = Some(pages.map(|idx| update[idx]).collect());
+            }
+            order.push(update[idx]);
+            for &next in &successors[idx] {
+                in_degree[next] -= 1;
+                if in_degree[next] == 0 {
+                    ready.push_back(next);
+                }
+            }
+        }
+
+        if order.len() < n {
+            return Err(OrderError::Cycle(find_cycle(
+                update,
+                &successors,
+                &in_degree,
+            )));
+        }
+        match tied {
+            Some(pages) => Err(OrderError::Ambiguous { order, pages }),
+            None => Ok(order),
+        }
+    }
+}
+
+/// Find a cycle among the pages Kahn's algorithm couldn't place.
+///
+/// Every unplaced page has a predecessor that is also unplaced, so walking
+/// backwards through unplaced predecessors must eventually repeat a page.
+fn find_cycle(update: &[u32], successors: &[Vec<usize>], in_degree: &[usize]) -> Vec<u32> {
+    let unplaced = |idx: usize| in_degree[idx] > 0;
+    let predecessor = |idx: usize| {
+        (0..update.len())
+            .find(|&p| unplaced(p) && successors[p].contains(&idx))
+            .expect("Unplaced pages always have an unplaced predecessor.")
+    };
+
+    let start = (0..update.len())
+        .find(|&idx| unplaced(idx))
+        .expect("There must be unplaced pages to find a cycle.");
+    let mut walked = vec![start];
+    let mut current = predecessor(start);
+    while !walked.contains(&current) {
+        walked.push(current);
+        current = predecessor(current);
+    }
+    let cycle_start = walked.iter().position(|&idx| idx == current).unwrap();
+    // The walk went backwards, so reverse it to read in rule order.
+    walked[cycle_start..]
         .iter()
-        .filter(|&update| !update_is_valid(rules, update))
-        .map(|unsorted| sort_update(rules, unsorted))
-        .map(|xs| middle_element(&xs))
-        .sum()
+        .rev()
+        .map(|&idx| update[idx])
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum OrderError {
+    /// The pages form a cycle, each one required to come before the next
+    /// and the last before the first.
+    Cycle(Vec<u32>),
+    /// The rules allow more than one order. `order` is one of them, and
+    /// `pages` the first set of pages whose relative order is left open.
+    Ambiguous { order: Vec<u32>, pages: Vec<u32> },
 }
 
-fn sort_update(rules: &Rules, update: &[u32]) -> Vec<u32> {
-    let mut update = update.to_vec();
-    update.sort_by(|a, b| {
-        let a_before_b = rules.get(a).is_some_and(|xs| xs.contains(b));
-        let b_before_a = rules.get(b).is_some_and(|xs| xs.contains(a));
+impl std::fmt::Display for OrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OrderError::Cycle(pages) => {
+                let pages = pages.iter().chain(pages.first()).join(" -> ");
+                write!(f, "Ordering rules form a cycle: {pages}")
+            }
+            OrderError::Ambiguous { pages, .. } => {
+                let pages = pages.iter().join(", ");
+                write!(f, "Ordering rules don't fix the order of pages {pages}")
+            }
+        }
+    }
+}
 
-        if a_before_b {
-            Ordering::Less
-        } else if b_before_a {
-            Ordering::Greater
-        } else {
-            Ordering::Equal
+impl std::error::Error for OrderError {}
+
+/// Page numbers are all two digits, so every rule fits in a 100×100 bit
+/// matrix and looking one up is a shift and a mask.
+const PAGE_LIMIT: u32 = 100;
+
+fn check_page(page: u32) -> anyhow::Result<u32> {
+    if page < PAGE_LIMIT {
+        Ok(page)
+    } else {
+        Err(anyhow!(
+            "Page number {page} is out of range, pages must be below {PAGE_LIMIT}."
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    /// Bit `after` of row `before` is set when `before` must precede `after`.
+    rows: [u128; PAGE_LIMIT as usize],
+}
+
+impl Rules {
+    fn new() -> Self {
+        Self {
+            rows: [0; PAGE_LIMIT as usize],
+        }
+    }
+
+    fn from_pairs(pairs: &[(u32, u32)]) -> anyhow::Result<Self> {
+        let mut rules = Self::new();
+        for &(before, after) in pairs {
+            rules.insert(before, after)?;
+        }
+        Ok(rules)
+    }
+
+    fn insert(&mut self, before: u32, after: u32) -> anyhow::Result<()> {
+        let (before, after) = (check_page(before)?, check_page(after)?);
+        self.rows[before as usize] |= 1 << after;
+        Ok(())
+    }
+
+    fn must_precede(&self, before: u32, after: u32) -> bool {
+        before < PAGE_LIMIT && after < PAGE_LIMIT && self.rows[before as usize] & (1 << after) != 0
+    }
+}
+
+/// Checks updates against ordering rules that arrive one at a time,
+/// interleaved with the updates themselves.
+///
+/// An update is judged against the rules seen so far, and judged again when
+/// a later rule mentions two of its pages. Rules are only ever added, so a
+/// new rule can make a valid update invalid but never the reverse.
+struct RulesEngine {
+    rules: Rules,
+    updates: Updates,
+    valid: Vec<bool>,
+    /// The indices of the updates each page appears in.
+    containing: Vec<Vec<usize>>,
+}
+
+impl RulesEngine {
+    fn new() -> Self {
+        Self {
+            rules: Rules::new(),
+            updates: Vec::new(),
+            valid: Vec::new(),
+            containing: vec![Vec::new(); PAGE_LIMIT as usize],
+        }
+    }
+
+    /// Add a rule, returning the indices of the updates it invalidates.
+    fn add_rule(&mut self, before: u32, after: u32) -> anyhow::Result<Vec<usize>> {
+        self.rules.insert(before, after)?;
+        let mut invalidated = Vec::new();
+        for &idx in &self.containing[before as usize] {
+            if !self.valid[idx] {
+                continue;
+            }
+            let update = &self.updates[idx];
+            let position = |page: u32| update.iter().position(|&p| p == page);
+            if let (Some(before_pos), Some(after_pos)) = (position(before), position(after)) {
+                if after_pos < before_pos {
+                    self.valid[idx] = false;
+                    invalidated.push(idx);
+                }
+            }
         }
-    });
-    update
+        Ok(invalidated)
+    }
+
+    /// Add an update, returning its index and whether it is valid under
+    /// the rules seen so far.
+    fn add_update(&mut self, update: Vec<u32>) -> anyhow::Result<(usize, bool)> {
+        for &page in &update {
+            check_page(page)?;
+        }
+        let idx = self.updates.len();
+        let valid = update_is_valid(&self.rules, &update);
+        for &page in &update {
+            self.containing[page as usize].push(idx);
+        }
+        self.updates.push(update);
+        self.valid.push(valid);
+        Ok((idx, valid))
+    }
+
+    fn is_valid(&self, idx: usize) -> bool {
+        self.valid[idx]
+    }
 }
 
 fn parse_input(input: &str) -> anyhow::Result<(Rules, Updates)> {
-    let (input, (rules, updates)) = parse::all(input).map_err(|e| e.to_owned())?;
+    let (input, (pairs, updates)) = parse::all(input).map_err(|e| e.to_owned())?;
     if !input.is_empty() {
         return Err(anyhow!("Leftover input: {input:?}"));
     }
+    let rules = Rules::from_pairs(&pairs)?;
+    for &page in updates.iter().flatten() {
+        check_page(page)?;
+    }
     Ok((rules, updates))
 }
 
@@ -68,10 +352,7 @@ fn middle_element(xs: &[u32]) -> u32 {
 fn update_is_valid(rules: &Rules, update: &[u32]) -> bool {
     for (idx, appears_first) in update.iter().enumerate() {
         for appears_later in &update[idx + 1..] {
-            let later_must_come_before_first = rules
-                .get(appears_later)
-                .is_some_and(|xs| xs.contains(appears_first));
-            if later_must_come_before_first {
+            if rules.must_precede(*appears_later, *appears_first) {
                 return false;
             }
         }
@@ -79,21 +360,135 @@ fn update_is_valid(rules: &Rules, update: &[u32]) -> bool {
     true
 }
 
-mod parse {
-    use std::collections::HashMap;
+/// A rule `before|after` broken by an update that puts `after` first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Violation {
+    before: u32,
+    after: u32,
+    before_pos: usize,
+    after_pos: usize,
+}
 
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}|{} broken: {} at position {}, {} at position {}",
+            self.before, self.after, self.after, self.after_pos, self.before, self.before_pos
+        )
+    }
+}
+
+/// Take `page` out of the update at position `from` and put it straight
+/// after the page `after`, or at the front if that's `None`.
+///
+/// `after` is the page just before this one once the update is in order.
+/// Moves are listed in that order too, so by the time a page moves, the page
+/// it follows is already where it belongs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Move {
+    page: u32,
+    from: usize,
+    after: Option<u32>,
+}
+
+impl std::fmt::Display for Move {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "move {} from position {} ", self.page, self.from)?;
+        match self.after {
+            Some(after) => write!(f, "to after {after}"),
+            None => write!(f, "to the front"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Explanation {
+    violations: Vec<Violation>,
+    /// The moves need the update's pages to sort, which fails on a cycle or
+    /// an ambiguous order even though the violations are still known.
+    moves: Result<Vec<Move>, OrderError>,
+}
+
+fn violated_rules(rules: &Rules, update: &[u32]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for (after_pos, &after) in update.iter().enumerate() {
+        for (before_pos, &before) in update.iter().enumerate().skip(after_pos + 1) {
+            if rules.must_precede(before, after) {
+                violations.push(Violation {
+                    before,
+                    after,
+                    before_pos,
+                    after_pos,
+                });
+            }
+        }
+    }
+    violations
+}
+
+/// The fewest moves that put an update in order.
+///
+/// The pages that can stay put are those forming the longest subsequence
+/// of the update that is already in sorted order; every other page moves.
+fn minimal_moves(rules: &Rules, update: &[u32]) -> Result<Vec<Move>, OrderError> {
+    let sorted = sort_update(rules, update)?;
+    let rank: Vec<usize> = update
+        .iter()
+        .map(|page| sorted.iter().position(|p| p == page).unwrap())
+        .collect();
+
+    // Longest increasing subsequence of ranks, O(n^2) as updates are short.
+    let n = update.len();
+    let mut length = vec![1_usize; n];
+    let mut previous: Vec<Option<usize>> = vec![None; n];
+    for idx in 0..n {
+        for earlier in 0..idx {
+            if rank[earlier] < rank[idx] && length[earlier] + 1 > length[idx] {
+                length[idx] = length[earlier] + 1;
+                previous[idx] = Some(earlier);
+            }
+        }
+    }
+    let mut stays = vec![false; n];
+    let mut cursor = (0..n).max_by_key(|&idx| length[idx]);
+    while let Some(idx) = cursor {
+        stays[idx] = true;
+        cursor = previous[idx];
+    }
+
+    let moves = (0..n)
+        .filter(|&idx| !stays[idx])
+        .sorted_by_key(|&idx| rank[idx])
+        .map(|idx| Move {
+            page: update[idx],
+            from: idx,
+            after: rank[idx].checked_sub(1).map(|before| sorted[before]),
+        })
+        .collect();
+    Ok(moves)
+}
+
+fn explain_update(rules: &Rules, update: &[u32]) -> Explanation {
+    Explanation {
+        violations: violated_rules(rules, update),
+        moves: minimal_moves(rules, update),
+    }
+}
+
+mod parse {
     use nom::{
         bytes::complete::tag,
         character::complete::{self, line_ending},
-        multi::{fold_many1, many0, separated_list1},
+        multi::{many0, many1, separated_list1},
         sequence::{separated_pair, terminated},
         IResult,
     };
 
-    pub type Rules = HashMap<u32, Vec<u32>>;
+    pub type RulePairs = Vec<(u32, u32)>;
     pub type Updates = Vec<Vec<u32>>;
 
-    pub fn all(input: &str) -> IResult<&str, (Rules, Updates)> {
+    pub fn all(input: &str) -> IResult<&str, (RulePairs, Updates)> {
         let (input, rules) = rules(input)?;
         let (input, _) = line_ending(input)?;
         let (input, updates) = updates(input)?;
@@ -101,28 +496,27 @@ mod parse {
         IResult::Ok((input, (rules, updates)))
     }
 
-    fn rules(input: &str) -> IResult<&str, Rules> {
-        fold_many1(
-            terminated(
-                separated_pair(complete::u32, tag("|"), complete::u32),
-                line_ending,
-            ),
-            HashMap::new,
-            |mut rules: Rules, (before, after)| {
-                rules.entry(before).or_default().push(after);
-                rules
-            },
-        )(input)
+    fn rules(input: &str) -> IResult<&str, RulePairs> {
+        many1(terminated(rule, line_ending))(input)
+    }
+
+    pub fn rule(input: &str) -> IResult<&str, (u32, u32)> {
+        separated_pair(complete::u32, tag("|"), complete::u32)(input)
     }
 
     fn updates(input: &str) -> IResult<&str, Updates> {
-        separated_list1(line_ending, separated_list1(tag(","), complete::u32))(input)
+        separated_list1(line_ending, update)(input)
+    }
+
+    pub fn update(input: &str) -> IResult<&str, Vec<u32>> {
+        separated_list1(tag(","), complete::u32)(input)
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::parse::Updates;
+    use super::{Move, OrderError, RulesEngine, Violation};
 
     const SAMPLE_INPUT: &str = "47|53
 97|13
@@ -157,8 +551,10 @@ mod test {
     fn parse_sample_input() -> anyhow::Result<()> {
         let (rules, updates) = super::parse_input(SAMPLE_INPUT)?;
 
-        let r97 = rules.get(&97).unwrap();
-        assert_eq!(r97, &[13, 61, 47, 29, 53, 75]);
+        let r97: Vec<u32> = (0..100).filter(|&p| rules.must_precede(97, p)).collect();
+        assert_eq!(r97, &[13, 29, 47, 53, 61, 75]);
+        assert!(rules.must_precede(97, 13));
+        assert!(!rules.must_precede(13, 97));
 
         let u = updates.first().unwrap();
         assert_eq!(u, &[75, 47, 61, 53, 29]);
@@ -169,6 +565,54 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn reject_out_of_range_pages() {
+        let err = super::parse_input("1|100\n\n1,100\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Page number 100 is out of range, pages must be below 100."
+        );
+        let err = super::parse_input("1|2\n\n1,2,345\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Page number 345 is out of range, pages must be below 100."
+        );
+    }
+
+    #[test]
+    fn engine_matches_batch_validity() -> anyhow::Result<()> {
+        let (rules, updates) = super::parse_input(SAMPLE_INPUT)?;
+        // Updates first, then rules, the opposite of the puzzle input.
+        let mut engine = RulesEngine::new();
+        for update in &updates {
+            let (_, valid) = engine.add_update(update.clone())?;
+            assert!(valid, "With no rules every update is valid.");
+        }
+        for (before, after) in SAMPLE_INPUT.lines().map_while(|l| l.split_once('|')) {
+            engine.add_rule(before.parse()?, after.parse()?)?;
+        }
+        for (idx, update) in updates.iter().enumerate() {
+            assert_eq!(engine.is_valid(idx), super::update_is_valid(&rules, update));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn engine_reports_invalidated_updates() -> anyhow::Result<()> {
+        let mut engine = RulesEngine::new();
+        assert_eq!(engine.add_update(vec![1, 2, 3])?, (0, true));
+        assert_eq!(engine.add_update(vec![3, 1])?, (1, true));
+        assert_eq!(engine.add_update(vec![2, 4])?, (2, true));
+        assert_eq!(engine.add_rule(1, 3)?, vec![1]);
+        assert_eq!(engine.add_rule(4, 2)?, vec![2]);
+        // Already invalid updates aren't reported again.
+        assert_eq!(engine.add_rule(2, 4)?, vec![]);
+        assert_eq!(engine.add_update(vec![3, 1])?, (3, false));
+        assert!(engine.is_valid(0));
+        assert!(engine.add_rule(1, 100).is_err());
+        Ok(())
+    }
+
     #[test]
     fn filter_sample_updates() -> anyhow::Result<()> {
         let (rules, updates) = super::parse_input(SAMPLE_INPUT)?;
@@ -198,21 +642,115 @@ mod test {
     #[test]
     fn sort_update_correctly() -> anyhow::Result<()> {
         let (rules, _) = super::parse_input(SAMPLE_INPUT)?;
-        let sorted = super::sort_update(&rules, &[61, 13, 29]);
+        let sorted = super::sort_update(&rules, &[61, 13, 29])?;
         assert_eq!(sorted, &[61, 29, 13]);
 
-        let sorted = super::sort_update(&rules, &[75, 97, 47, 61, 53]);
+        let sorted = super::sort_update(&rules, &[75, 97, 47, 61, 53])?;
         assert_eq!(sorted, &[97, 75, 47, 61, 53]);
 
-        let sorted = super::sort_update(&rules, &[97, 13, 75, 29, 47]);
+        let sorted = super::sort_update(&rules, &[97, 13, 75, 29, 47])?;
         assert_eq!(sorted, &[97, 75, 47, 29, 13]);
         Ok(())
     }
 
+    #[test]
+    fn explain_sample_update() -> anyhow::Result<()> {
+        let (rules, _) = super::parse_input(SAMPLE_INPUT)?;
+        let explanation = super::explain_update(&rules, &[97, 13, 75, 29, 47]);
+        let broken: Vec<(u32, u32)> = explanation
+            .violations
+            .iter()
+            .map(|v| (v.before, v.after))
+            .collect();
+        assert_eq!(broken, [(75, 13), (29, 13), (47, 13), (47, 29)]);
+        assert_eq!(
+            explanation.violations[0],
+            Violation {
+                before: 75,
+                after: 13,
+                before_pos: 2,
+                after_pos: 1,
+            }
+        );
+        // 97, 75 and 47 are already in order, so only 13 and 29 need moving.
+        assert_eq!(
+            explanation.moves.clone()?,
+            [
+                Move {
+                    page: 29,
+                    from: 3,
+                    after: Some(47),
+                },
+                Move {
+                    page: 13,
+                    from: 1,
+                    after: Some(29),
+                },
+            ]
+        );
+        let moves = explanation.moves?;
+        assert_eq!(moves[0].to_string(), "move 29 from position 3 to after 47");
+        let to_front = super::explain_update(&rules, &[75, 97, 47, 61, 53]).moves?;
+        assert_eq!(
+            to_front[0].to_string(),
+            "move 97 from position 1 to the front"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn valid_updates_need_no_moves() -> anyhow::Result<()> {
+        let (rules, _) = super::parse_input(SAMPLE_INPUT)?;
+        let explanation = super::explain_update(&rules, &[75, 47, 61, 53, 29]);
+        assert!(explanation.violations.is_empty());
+        assert!(explanation.moves?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn explain_keeps_violations_when_sorting_fails() -> anyhow::Result<()> {
+        let (rules, _) = super::parse_input("1|2\n2|3\n3|1\n\n1,2,3\n")?;
+        let explanation = super::explain_update(&rules, &[3, 2, 1]);
+        let broken: Vec<(u32, u32)> = explanation
+            .violations
+            .iter()
+            .map(|v| (v.before, v.after))
+            .collect();
+        assert_eq!(broken, [(2, 3), (1, 2)]);
+        assert_eq!(explanation.moves, Err(OrderError::Cycle(vec![1, 2, 3])));
+        Ok(())
+    }
+
+    #[test]
+    fn sort_detects_cycles() -> anyhow::Result<()> {
+        let (rules, _) = super::parse_input("1|2\n2|3\n3|1\n3|4\n\n1,2,3,4\n")?;
+        let err = super::sort_update(&rules, &[4, 3, 2, 1]).unwrap_err();
+        assert_eq!(err, OrderError::Cycle(vec![1, 2, 3]));
+        assert_eq!(
+            err.to_string(),
+            "Ordering rules form a cycle: 1 -> 2 -> 3 -> 1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sort_detects_ambiguous_orders() -> anyhow::Result<()> {
+        let (rules, _) = super::parse_input("1|2\n1|3\n\n1,2,3\n")?;
+        let err = super::sort_update(&rules, &[3, 2, 1]).unwrap_err();
+        assert_eq!(
+            err,
+            OrderError::Ambiguous {
+                order: vec![1, 3, 2],
+                pages: vec![3, 2],
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn solve_sample_part_two() -> anyhow::Result<()> {
         let (rules, updates) = super::parse_input(SAMPLE_INPUT)?;
-        let sum = super::part_two(&rules, &updates);
+        let sum = super::part_two(&rules, &updates)?;
         assert_eq!(sum, 123);
         Ok(())
     }
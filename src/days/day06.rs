@@ -1,6 +1,9 @@
 use std::collections::HashSet;
 use std::str::FromStr;
 
+use anyhow::anyhow;
+use rayon::prelude::*;
+
 use crate::util::Answer;
 
 pub fn solve(input: &str) -> anyhow::Result<String> {
@@ -9,12 +12,62 @@ pub fn solve(input: &str) -> anyhow::Result<String> {
     let p1 = count_visited_positions(&grid);
     assert_eq!(p1, 4722, "Part one is not the correct answer.");
 
-    let p2 = count_loops_with_new_walls(&grid);
+    let p2 = count_loops_with_new_walls(&grid, Mode::Parallel);
     assert_eq!(p2, 1602, "Part two is not the correct answer.");
 
     Answer::first(6, p1).second(p2).report()
 }
 
+/// Draw the guard's route and the new walls that would cause a loop.
+///
+/// Options: `--arrows` draws the guard's heading instead of `X`,
+/// `--serial` checks for loops on a single thread, `--turn <right|left|reverse>`
+/// changes what guards do at walls, and `--guards` lists what happens to
+/// every guard on the map instead of drawing the first one's route.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    _out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    let mut grid: Grid = input.parse()?;
+    let mut style = PathStyle::Visited;
+    let mut mode = Mode::Parallel;
+    let mut list_guards = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--arrows" => style = PathStyle::Arrows,
+            "--serial" => mode = Mode::Serial,
+            "--guards" => list_guards = true,
+            "--turn" => {
+                let rule = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--turn needs a rule: right, left or reverse"))?;
+                grid = grid.with_turn_rule(rule.parse()?);
+            }
+            other => return Err(anyhow!("Unknown option for day 6: {other}")),
+        }
+    }
+    if list_guards {
+        let lines: Vec<String> = guard_outcomes(&grid)
+            .into_iter()
+            .map(|g| {
+                let ((pos, dir), ending) = (g.start, if g.loops { "loops" } else { "leaves" });
+                format!(
+                    "({}, {}) {}: {} positions, {ending}",
+                    pos.row,
+                    pos.col,
+                    dir.arrow(),
+                    g.visited
+                )
+            })
+            .collect();
+        return Ok(lines.join("\n"));
+    }
+    let report = patrol_report(&grid, mode);
+    Ok(render(&grid, &report, style))
+}
+
 fn new_hashset<T>(capacity: usize) -> HashSet<T, foldhash::fast::RandomState> {
     HashSet::with_capacity_and_hasher(capacity, foldhash::fast::RandomState::default())
 }
@@ -40,6 +93,16 @@ enum Direction {
 }
 
 impl Direction {
+    fn from_marker(marker: char) -> Option<Self> {
+        match marker {
+            '^' => Some(Direction::North),
+            '>' => Some(Direction::East),
+            'v' => Some(Direction::South),
+            '<' => Some(Direction::West),
+            _ => None,
+        }
+    }
+
     fn turn_right(&self) -> Self {
         match self {
             Direction::North => Direction::East,
@@ -48,18 +111,74 @@ impl Direction {
             Direction::West => Direction::North,
         }
     }
+
+    fn turn_left(&self) -> Self {
+        self.turn_right().turn_right().turn_right()
+    }
+
+    fn reverse(&self) -> Self {
+        self.turn_right().turn_right()
+    }
+
+    fn arrow(&self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::East => '>',
+            Direction::South => 'v',
+            Direction::West => '<',
+        }
+    }
+}
+
+/// What a guard does on walking into a wall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum TurnRule {
+    #[default]
+    Right,
+    Left,
+    Reverse,
+}
+
+impl TurnRule {
+    fn apply(&self, direction: Direction) -> Direction {
+        match self {
+            TurnRule::Right => direction.turn_right(),
+            TurnRule::Left => direction.turn_left(),
+            TurnRule::Reverse => direction.reverse(),
+        }
+    }
+}
+
+impl FromStr for TurnRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "right" => Ok(TurnRule::Right),
+            "left" => Ok(TurnRule::Left),
+            "reverse" => Ok(TurnRule::Reverse),
+            other => Err(anyhow!("Unknown turn rule: {other:?}")),
+        }
+    }
 }
 
 struct Grid {
     walls: HashSet<Position, foldhash::fast::RandomState>,
-    start_position: Position,
+    /// Each guard's starting position and heading, in reading order.
+    guards: Vec<(Position, Direction)>,
+    turn_rule: TurnRule,
     n_rows: usize,
     n_cols: usize,
 }
 
 impl Grid {
+    /// The first guard's starting state. The puzzle itself only has one.
     fn start_position(&self) -> (Position, Direction) {
-        (self.start_position, Direction::North)
+        self.guards[0]
+    }
+
+    fn with_turn_rule(self, turn_rule: TurnRule) -> Self {
+        Self { turn_rule, ..self }
     }
 
     fn in_bounds(&self, position: Position) -> bool {
@@ -86,11 +205,17 @@ impl Grid {
         position: Position,
         direction: Direction,
     ) -> Option<(Position, Direction)> {
-        let next_pos = self.move_one(position, direction)?;
-        if self.walls.contains(&next_pos) {
-            return self.next_position(position, direction.turn_right());
+        let mut direction = direction;
+        // A guard surrounded by walls tries every heading their turn rule
+        // allows, and if they're all blocked is left turning on the spot.
+        for _ in 0..4 {
+            let next_pos = self.move_one(position, direction)?;
+            if !self.walls.contains(&next_pos) {
+                return Some((next_pos, direction));
+            }
+            direction = self.turn_rule.apply(direction);
         }
-        Some((next_pos, direction))
+        Some((position, direction))
     }
 
     fn next_position_at_wall(
@@ -102,7 +227,7 @@ impl Grid {
         let mut pos = position;
         while let Some(next_pos) = self.move_one(pos, direction) {
             if next_pos == extra_wall || self.walls.contains(&next_pos) {
-                return Some((pos, direction.turn_right()));
+                return Some((pos, self.turn_rule.apply(direction)));
             }
             pos = next_pos;
         }
@@ -114,7 +239,7 @@ impl FromStr for Grid {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut start: Option<Position> = None;
+        let mut guards = Vec::new();
         let mut walls = new_hashset(1024);
 
         let n_rows = s.lines().count();
@@ -126,22 +251,150 @@ impl FromStr for Grid {
 
         for (row_idx, row) in s.lines().enumerate() {
             for (col_idx, element) in row.chars().enumerate() {
+                let position = Position::new(row_idx, col_idx);
                 if element == '#' {
-                    walls.insert(Position::new(row_idx, col_idx));
-                } else if element == '^' {
-                    start = Some(Position::new(row_idx, col_idx));
+                    walls.insert(position);
+                } else if let Some(direction) = Direction::from_marker(element) {
+                    guards.push((position, direction));
                 }
             }
         }
+        if guards.is_empty() {
+            return Err(anyhow!("No guard start position found."));
+        }
         Ok(Self {
             walls,
-            start_position: start.expect("No start position found."),
+            guards,
+            turn_rule: TurnRule::default(),
             n_rows,
             n_cols,
         })
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Step {
+    index: usize,
+    position: Position,
+    direction: Direction,
+}
+
+/// A guard's route from `start`, which ends either when they leave the map
+/// or when they return to a state they've been in before (and so loop).
+struct Walk {
+    states: Vec<(Position, Direction)>,
+    loops: bool,
+}
+
+fn walk(grid: &Grid, start: (Position, Direction)) -> Walk {
+    let mut seen = new_hashset(4800);
+    let mut states = Vec::with_capacity(4800);
+    let mut state = Some(start);
+    while let Some((pos, dir)) = state {
+        if !seen.insert((pos, dir)) {
+            return Walk {
+                states,
+                loops: true,
+            };
+        }
+        states.push((pos, dir));
+        state = grid.next_position(pos, dir);
+    }
+    Walk {
+        states,
+        loops: false,
+    }
+}
+
+/// What happened to one of the guards on the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct GuardOutcome {
+    start: (Position, Direction),
+    visited: usize,
+    loops: bool,
+}
+
+/// Walk each guard independently; guards don't block each other.
+fn guard_outcomes(grid: &Grid) -> Vec<GuardOutcome> {
+    grid.guards
+        .iter()
+        .map(|&start| {
+            let Walk { states, loops } = walk(grid, start);
+            let visited: HashSet<Position> = states.iter().map(|&(pos, _)| pos).collect();
+            GuardOutcome {
+                start,
+                visited: visited.len(),
+                loops,
+            }
+        })
+        .collect()
+}
+
+/// Every step the first guard takes, starting with where they stand at the
+/// start. A step's direction is the heading after any turns made on that step.
+fn trace_path(grid: &Grid) -> Vec<Step> {
+    walk(grid, grid.start_position())
+        .states
+        .into_iter()
+        .enumerate()
+        .map(|(index, (position, direction))| Step {
+            index,
+            position,
+            direction,
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+struct PatrolReport {
+    path: Vec<Step>,
+    loop_walls: Vec<Position>,
+}
+
+fn patrol_report(grid: &Grid, mode: Mode) -> PatrolReport {
+    PatrolReport {
+        path: trace_path(grid),
+        loop_walls: find_loop_walls(grid, mode),
+    }
+}
+
+/// How the guard's route is drawn when rendering a [`PatrolReport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathStyle {
+    /// `X` for every visited position, as in the part one illustrations.
+    Visited,
+    /// The heading the guard last left each visited position with.
+    Arrows,
+}
+
+fn render(grid: &Grid, report: &PatrolReport, style: PathStyle) -> String {
+    let mut cells = vec![vec!['.'; grid.n_cols]; grid.n_rows];
+    for wall in &grid.walls {
+        cells[wall.row][wall.col] = '#';
+    }
+    // A step's direction is the heading it arrived with, after any turn,
+    // so the heading it leaves with is that of the following step.
+    let leaving = report
+        .path
+        .iter()
+        .skip(1)
+        .chain(report.path.last())
+        .map(|s| s.direction);
+    for (step, heading) in report.path.iter().zip(leaving) {
+        cells[step.position.row][step.position.col] = match style {
+            PathStyle::Visited => 'X',
+            PathStyle::Arrows => heading.arrow(),
+        };
+    }
+    for wall in &report.loop_walls {
+        cells[wall.row][wall.col] = 'O';
+    }
+    cells
+        .into_iter()
+        .map(|row| row.into_iter().chain(['\n']).collect::<String>())
+        .collect()
+}
+
 fn count_visited_positions(grid: &Grid) -> usize {
     let (mut pos, mut dir) = grid.start_position();
     let mut visited = new_hashset(4800);
@@ -153,22 +406,47 @@ fn count_visited_positions(grid: &Grid) -> usize {
     visited.len()
 }
 
-fn count_loops_with_new_walls(grid: &Grid) -> u32 {
-    let (mut pos, mut dir) = grid.start_position();
+/// Whether candidate walls are checked for loops on the rayon thread pool
+/// or one after another (deterministic, and easier to step through).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Serial,
+    Parallel,
+}
+
+/// Each position on the guard's original route where a new wall could be
+/// placed, paired with the state the guard is in when it first bumps into it.
+fn wall_candidates(grid: &Grid) -> Vec<(Position, (Position, Direction))> {
+    let states = walk(grid, grid.start_position()).states;
     let mut visited = new_hashset(4800);
-    visited.insert(pos);
-    let mut loops_found = 0;
-    while let Some((next_pos, next_dir)) = grid.next_position(pos, dir) {
+    visited.insert(states[0].0);
+    let mut candidates = Vec::with_capacity(4800);
+    for (&(pos, dir), &(next_pos, _)) in states.iter().zip(&states[1..]) {
         // Checking the position only is fine (rather than (position, direction))
         // because if there's a wall there, it's there from the start, so
         // only the first encounter matters.
-        if !visited.contains(&next_pos) && check_for_loop(grid, next_pos, (pos, dir)) {
-            loops_found += 1;
+        if visited.insert(next_pos) {
+            candidates.push((next_pos, (pos, dir)));
         }
-        (pos, dir) = (next_pos, next_dir);
-        visited.insert(pos);
     }
-    loops_found
+    candidates
+}
+
+fn count_loops_with_new_walls(grid: &Grid, mode: Mode) -> usize {
+    find_loop_walls(grid, mode).len()
+}
+
+/// Positions of new walls that would trap the guard in a loop, in the order
+/// the guard reaches them on the original route.
+fn find_loop_walls(grid: &Grid, mode: Mode) -> Vec<Position> {
+    let candidates = wall_candidates(grid);
+    let causes_loop = |&(extra_wall, start_at): &(Position, (Position, Direction))| {
+        check_for_loop(grid, extra_wall, start_at).then_some(extra_wall)
+    };
+    match mode {
+        Mode::Serial => candidates.iter().filter_map(causes_loop).collect(),
+        Mode::Parallel => candidates.par_iter().filter_map(causes_loop).collect(),
+    }
 }
 
 fn check_for_loop(grid: &Grid, extra_wall: Position, start_at: (Position, Direction)) -> bool {
@@ -187,7 +465,10 @@ fn check_for_loop(grid: &Grid, extra_wall: Position, start_at: (Position, Direct
 
 #[cfg(test)]
 mod test {
-    use super::{count_loops_with_new_walls, count_visited_positions, Direction, Grid, Position};
+    use super::{
+        count_loops_with_new_walls, count_visited_positions, guard_outcomes, patrol_report, render,
+        trace_path, Direction, Grid, GuardOutcome, Mode, PathStyle, Position, TurnRule,
+    };
 
     const SAMPLE_INPUT: &str = "\
 ....#.....
@@ -225,8 +506,146 @@ mod test {
     #[test]
     fn sample_grid_find_loops() -> anyhow::Result<()> {
         let grid: Grid = SAMPLE_INPUT.parse()?;
-        let n_loops = count_loops_with_new_walls(&grid);
+        let n_loops = count_loops_with_new_walls(&grid, Mode::Parallel);
         assert_eq!(n_loops, 6);
         Ok(())
     }
+
+    #[test]
+    fn serial_and_parallel_loop_counts_agree() -> anyhow::Result<()> {
+        let grid: Grid = crate::PUZZLE_INPUT[5].parse()?;
+        assert_eq!(
+            count_loops_with_new_walls(&grid, Mode::Serial),
+            count_loops_with_new_walls(&grid, Mode::Parallel)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn trace_sample_path() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let path = trace_path(&grid);
+        let first = path.first().unwrap();
+        assert_eq!(
+            (first.index, first.position, first.direction),
+            (0, Position::new(6, 4), Direction::North)
+        );
+        // The guard turns right on reaching the wall at (0, 4).
+        let turn = path[6];
+        assert_eq!(
+            (turn.index, turn.position, turn.direction),
+            (6, Position::new(1, 5), Direction::East)
+        );
+        let last = path.last().unwrap();
+        assert_eq!(last.position, Position::new(9, 7));
+        Ok(())
+    }
+
+    #[test]
+    fn sample_loop_walls() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let mut walls = patrol_report(&grid, Mode::Serial).loop_walls;
+        walls.sort();
+        assert_eq!(
+            walls,
+            [(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)].map(|(r, c)| Position::new(r, c))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn render_sample_visited() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let mut report = patrol_report(&grid, Mode::Serial);
+        report.loop_walls.clear();
+        let expected = "\
+....#.....
+....XXXXX#
+....X...X.
+..#.X...X.
+..XXXXX#X.
+..X.X.X.X.
+.#XXXXXXX.
+.XXXXXXX#.
+#XXXXXXX..
+......#X..
+";
+        assert_eq!(render(&grid, &report, PathStyle::Visited), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn render_sample_arrows_with_walls() -> anyhow::Result<()> {
+        let grid: Grid = SAMPLE_INPUT.parse()?;
+        let report = patrol_report(&grid, Mode::Serial);
+        let rendered = render(&grid, &report, PathStyle::Arrows);
+        let rows: Vec<&str> = rendered.lines().collect();
+        assert_eq!(rows[1], "....>>>>v#");
+        assert_eq!(rows[6], ".#^O<<v<<.");
+        assert_eq!(rows[9], "......#O..");
+        Ok(())
+    }
+
+    #[test]
+    fn parse_guards_with_any_heading() -> anyhow::Result<()> {
+        let grid: Grid = "..>.\n#...\n...<\n.v..\n".parse()?;
+        assert_eq!(
+            grid.guards,
+            [
+                (Position::new(0, 2), Direction::East),
+                (Position::new(2, 3), Direction::West),
+                (Position::new(3, 1), Direction::South),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sample_grid_turning_left() -> anyhow::Result<()> {
+        let grid = SAMPLE_INPUT.parse::<Grid>()?.with_turn_rule(TurnRule::Left);
+        let path = trace_path(&grid);
+        // Heading west from (1, 4) rather than east.
+        assert_eq!(path[6].position, Position::new(1, 3));
+        assert_eq!(path[6].direction, Direction::West);
+        Ok(())
+    }
+
+    #[test]
+    fn guards_bounce_between_walls_when_reversing() -> anyhow::Result<()> {
+        let grid: Grid = "#..^...#\n........\n..v.....\n".parse()?;
+        let grid = grid.with_turn_rule(TurnRule::Reverse);
+        let outcomes = guard_outcomes(&grid);
+        assert_eq!(
+            outcomes,
+            [
+                GuardOutcome {
+                    start: (Position::new(0, 3), Direction::North),
+                    visited: 1,
+                    loops: false,
+                },
+                GuardOutcome {
+                    start: (Position::new(2, 2), Direction::South),
+                    visited: 1,
+                    loops: false,
+                },
+            ]
+        );
+
+        let grid = "#.>...#\n"
+            .parse::<Grid>()?
+            .with_turn_rule(TurnRule::Reverse);
+        let outcome = guard_outcomes(&grid)[0];
+        assert!(outcome.loops);
+        assert_eq!(outcome.visited, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn boxed_in_guard_loops() -> anyhow::Result<()> {
+        let grid: Grid = ".#.\n#^#\n.#.\n".parse()?;
+        let outcome = guard_outcomes(&grid)[0];
+        assert!(outcome.loops);
+        assert_eq!(outcome.visited, 1);
+        Ok(())
+    }
 }
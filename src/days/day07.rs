@@ -1,115 +1,937 @@
-use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
 
 use anyhow::anyhow;
-use itertools::Itertools;
+use foldhash::{HashMap, HashMapExt};
 use nom::{
     bytes::complete::tag,
-    character::complete::{self, line_ending, space1},
+    character::complete::{digit1, line_ending, space1},
+    combinator::map_res,
     multi::{many0, separated_list1},
     sequence::{separated_pair, terminated},
     IResult,
 };
+use num_bigint::BigUint;
 use rayon::prelude::*;
 
 use crate::util::Answer;
 
-fn generate_operators(numbers_length: usize, operators: Vec<Symbol>) -> Vec<Vec<Symbol>> {
-    let n_operators = numbers_length - 1;
-    (0..n_operators)
-        .map(|_| operators.clone())
-        .multi_cartesian_product()
-        .collect()
-}
-
-fn simple_operators() -> HashMap<usize, Vec<Vec<Symbol>>> {
-    (2..=12)
-        .map(|numbers_length| {
-            (
-                numbers_length,
-                generate_operators(numbers_length, vec![Symbol::Add, Symbol::Multiply]),
-            )
-        })
-        .collect()
-}
-
-fn complex_operators() -> HashMap<usize, Vec<Vec<Symbol>>> {
-    (2..=12)
-        .map(|numbers_length| {
-            (
-                numbers_length,
-                generate_operators(
-                    numbers_length,
-                    vec![Symbol::Add, Symbol::Multiply, Symbol::Concat],
-                ),
-            )
-        })
-        .collect()
-}
-
 pub fn solve(input: &str) -> anyhow::Result<String> {
-    let calibrations = parse_calibrations(input)?;
+    let calibrations = parse_calibrations::<u64>(input)?;
 
-    let p1 = part_one(&calibrations);
+    let p1 = part_one(&calibrations)?;
     assert_eq!(p1, 2501605301465, "Part one is not correct.");
 
-    let p2 = part_two(&calibrations);
+    let p2 = part_two(&calibrations)?;
     assert_eq!(p2, 44841372855953, "Part two is not correct.");
 
     Answer::first(7, p1).second(p2).report()
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Options: `--operators <list>` picks the operators to try, from `+`,
+/// `*`, `||`, `-`, `/`, `^` and `||<base>`, separated by commas (the
+/// default is `+,*`). By default the first way of making each equation true
+/// is printed, `--all` prints every way, and `--count` prints how many ways
+/// there are instead. `--precision <u64|big>` sets the integer type the
+/// equations are worked out in, and `--evaluation <left|precedence|brackets>`
+/// how their operators are grouped. With `brackets`, the first way is
+/// printed with one bracketing that makes it true; `--all` and `--count`
+/// aren't supported, as they would mean trying every choice of operators.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    _out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    let mut operators = "+,*";
+    let mut which = Solutions::First;
+    let mut count = false;
+    let mut precision = Precision::default();
+    let mut evaluation = Evaluation::default();
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--operators" => {
+                operators = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--operators needs a list of operators"))?;
+            }
+            "--all" => which = Solutions::All,
+            "--count" => count = true,
+            "--precision" => {
+                let value = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--precision needs a value: u64 or big"))?;
+                precision = value.parse()?;
+            }
+            "--evaluation" => {
+                let value = options.next().ok_or_else(|| {
+                    anyhow!("--evaluation needs a value: left, precedence or brackets")
+                })?;
+                evaluation = value.parse()?;
+            }
+            other => return Err(anyhow!("Unknown option for day 7: {other}")),
+        }
+    }
+    let settings = (which, count, evaluation);
+    match precision {
+        Precision::U64 => list_equations::<u64>(input, operators, settings),
+        Precision::Big => list_equations::<BigUint>(input, operators, settings),
+    }
+}
+
+fn list_equations<N: Number>(
+    input: &str,
+    operators: &str,
+    (which, count, evaluation): (Solutions, bool, Evaluation),
+) -> anyhow::Result<String> {
+    if evaluation == Evaluation::AnyBracketing && (count || which == Solutions::All) {
+        return Err(anyhow!(
+            "--all and --count can't be used with --evaluation brackets"
+        ));
+    }
+    let calibrations = parse_calibrations::<N>(input)?;
+    let operators: OperatorSet<N> = operators.parse()?;
+    let described: Vec<_> = calibrations
+        .par_iter()
+        .map(|(test_value, nums)| {
+            describe_equation(test_value, nums, &operators, (which, count, evaluation))
+        })
+        .collect();
+    let mut lines = Vec::new();
+    let mut n_true = 0;
+    for (made_true, equation_lines) in described {
+        n_true += usize::from(made_true);
+        lines.extend(equation_lines);
+    }
+    lines.push(format!(
+        "{n_true} of {} equations can be made true",
+        calibrations.len()
+    ));
+    Ok(lines.join("\n"))
+}
+
+/// Whether `nums` can make `test_value`, and the lines listing how.
+fn describe_equation<N: Number>(
+    test_value: &N,
+    nums: &[N],
+    operators: &OperatorSet<N>,
+    (which, count, evaluation): (Solutions, bool, Evaluation),
+) -> (bool, Vec<String>) {
+    if count {
+        let n_ways = count_assignments(test_value, nums, operators, evaluation);
+        return (n_ways > 0, vec![format!("{test_value}: {n_ways}")]);
+    }
+    if evaluation == Evaluation::AnyBracketing {
+        let all_operators: Vec<_> = operators.iter().collect();
+        let gaps = vec![all_operators; nums.len().saturating_sub(1)];
+        let bracketings = Bracketings::new(nums, &gaps, Some(test_value));
+        return match bracketings.expression(test_value) {
+            Some(expression) => (true, vec![format!("{test_value} = {expression}")]),
+            None => (false, Vec::new()),
+        };
+    }
+    let assignments = find_assignments(test_value, nums, operators, evaluation, which);
+    let lines: Vec<_> = assignments
+        .into_iter()
+        .map(|operators| {
+            Equation {
+                test_value,
+                nums,
+                operators,
+            }
+            .to_string()
+        })
+        .collect();
+    (!lines.is_empty(), lines)
+}
+
+/// The integer type calibrations are worked out in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Precision {
+    #[default]
+    U64,
+    /// Arbitrary precision, which never overflows.
+    Big,
+}
+
+impl FromStr for Precision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "u64" => Ok(Precision::U64),
+            "big" => Ok(Precision::Big),
+            other => Err(anyhow!("Unknown precision {other:?}, expected u64 or big.")),
+        }
+    }
+}
+
+/// How the operators of an equation are grouped when working it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Evaluation {
+    /// Strictly left to right, as the puzzle has it.
+    #[default]
+    LeftToRight,
+    /// Tighter binding operators first, so `*` before `+`, and otherwise
+    /// left to right.
+    Precedence,
+    /// Any bracketing at all.
+    AnyBracketing,
+}
+
+impl FromStr for Evaluation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "left" => Ok(Evaluation::LeftToRight),
+            "precedence" => Ok(Evaluation::Precedence),
+            "brackets" => Ok(Evaluation::AnyBracketing),
+            other => Err(anyhow!(
+                "Unknown evaluation {other:?}, expected left, precedence or brackets."
+            )),
+        }
+    }
+}
+
+/// The arithmetic the solver needs from the integers calibrations are
+/// written in. It's all checked, giving `None` for results that don't fit
+/// rather than wrapping or panicking.
+trait Number: Clone + Ord + Hash + From<u64> + FromStr + fmt::Display + Send + Sync {
+    fn checked_add(&self, rhs: &Self) -> Option<Self>;
+    fn checked_sub(&self, rhs: &Self) -> Option<Self>;
+    fn checked_mul(&self, rhs: &Self) -> Option<Self>;
+    /// The quotient and remainder, or `None` when dividing by zero.
+    fn checked_div_rem(&self, rhs: &Self) -> Option<(Self, Self)>;
+    fn checked_pow(&self, exp: &Self) -> Option<Self>;
+
+    fn is_zero(&self) -> bool {
+        *self == Self::from(0)
+    }
+}
+
+impl Number for u64 {
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        u64::checked_add(*self, *rhs)
+    }
+
+    fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        u64::checked_sub(*self, *rhs)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        u64::checked_mul(*self, *rhs)
+    }
+
+    fn checked_div_rem(&self, rhs: &Self) -> Option<(Self, Self)> {
+        Some((u64::checked_div(*self, *rhs)?, self % rhs))
+    }
+
+    fn checked_pow(&self, exp: &Self) -> Option<Self> {
+        u64::checked_pow(*self, u32::try_from(*exp).ok()?)
+    }
+}
+
+impl Number for BigUint {
+    fn checked_add(&self, rhs: &Self) -> Option<Self> {
+        Some(self + rhs)
+    }
+
+    fn checked_sub(&self, rhs: &Self) -> Option<Self> {
+        (self >= rhs).then(|| self - rhs)
+    }
+
+    fn checked_mul(&self, rhs: &Self) -> Option<Self> {
+        Some(self * rhs)
+    }
+
+    fn checked_div_rem(&self, rhs: &Self) -> Option<(Self, Self)> {
+        (!rhs.is_zero()).then(|| (self / rhs, self % rhs))
+    }
+
+    /// Only exponents that fit in a `u32`, past which the result wouldn't
+    /// fit in memory anyway.
+    fn checked_pow(&self, exp: &Self) -> Option<Self> {
+        Some(self.pow(u32::try_from(exp).ok()?))
+    }
+}
+
+/// A binary operator that can go between two numbers of a calibration.
+/// `lhs` and `rhs` are the values of the sub-expressions either side of it,
+/// which depend on the [`Evaluation`]: left to right, `lhs` is the running
+/// total so far and `rhs` the next number, but with precedence or brackets
+/// either side can be a whole group of numbers.
+trait Operator<N>: fmt::Display + Send + Sync {
+    /// `lhs` combined with `rhs`, or `None` if that doesn't give an `N`.
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N>;
+
+    /// What the left-hand side must have been for this operator to combine
+    /// it with `rhs` and give `target`. Left to right, the search works
+    /// backwards from the test value with this, and operators that can't
+    /// say fall back to trying every assignment of the numbers before them.
+    fn undo(&self, _target: &N, _rhs: &N) -> Undo<N> {
+        Undo::Unknown
+    }
+
+    /// How tightly this operator binds when evaluating with
+    /// [`Evaluation::Precedence`]: `+` and `-` are 1, `*` and `/` are 2,
+    /// and `^` and the concatenations, which glue numbers together, are 3.
+    fn precedence(&self) -> u8 {
+        1
+    }
+
+    /// Whether the result is never smaller than either side, as long as
+    /// neither is zero. Bracketing searches only keep values up to the
+    /// target when every operator is like this.
+    fn never_shrinks(&self) -> bool {
+        false
+    }
+}
+
+/// The left-hand side an operator needs to reach a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Undo<N> {
+    /// No left-hand side reaches the target.
+    Impossible,
+    /// Only this left-hand side reaches the target.
+    Lhs(N),
+    /// Any number of left-hand sides might, so evaluate forwards instead.
+    Unknown,
+}
+
+impl<N> From<Option<N>> for Undo<N> {
+    fn from(lhs: Option<N>) -> Self {
+        lhs.map_or(Undo::Impossible, Undo::Lhs)
+    }
+}
+
+/// The puzzle's own operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Symbol {
     Add,
     Multiply,
     Concat,
 }
 
-fn calibrate_one_sequence(test_value: u64, nums: &[u64], symbols: &[Symbol]) -> bool {
-    let mut nums = nums.iter();
-    let mut total = *nums.next().unwrap();
-    for (sym, rhs) in symbols.iter().zip(nums) {
-        if total > test_value {
-            return false;
+impl<N: Number> Operator<N> for Symbol {
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N> {
+        match self {
+            Symbol::Add => lhs.checked_add(rhs),
+            Symbol::Multiply => lhs.checked_mul(rhs),
+            Symbol::Concat => concat(lhs, rhs, &N::from(10)),
+        }
+    }
+
+    /// Since these operators only ever grow the running total, undoing them
+    /// prunes most of the search: `*` needs `rhs` to divide `target`, `+`
+    /// needs `rhs` to be no bigger than it, and `||` needs `target` to end
+    /// in the digits of `rhs`.
+    fn undo(&self, target: &N, rhs: &N) -> Undo<N> {
+        match self {
+            Symbol::Add => target.checked_sub(rhs).into(),
+            // Anything times zero is zero, so the left-hand side could be
+            // anything at all.
+            Symbol::Multiply if rhs.is_zero() && target.is_zero() => Undo::Unknown,
+            Symbol::Multiply => target
+                .checked_div_rem(rhs)
+                .and_then(|(lhs, remainder)| remainder.is_zero().then_some(lhs))
+                .into(),
+            Symbol::Concat => unconcat(target, rhs, &N::from(10)).into(),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Symbol::Add => 1,
+            Symbol::Multiply => 2,
+            Symbol::Concat => 3,
+        }
+    }
+
+    fn never_shrinks(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Symbol::Add => "+",
+            Symbol::Multiply => "*",
+            Symbol::Concat => "||",
+        };
+        f.write_str(symbol)
+    }
+}
+
+/// The power of `base` that shifts a number left past the digits of `rhs`.
+/// Zero is written with one digit, like any other number below `base`.
+fn concat_shift<N: Number>(rhs: &N, base: &N) -> Option<N> {
+    let mut shift = base.clone();
+    while shift <= *rhs {
+        shift = shift.checked_mul(base)?;
+    }
+    Some(shift)
+}
+
+fn concat<N: Number>(lhs: &N, rhs: &N, base: &N) -> Option<N> {
+    lhs.checked_mul(&concat_shift(rhs, base)?)?.checked_add(rhs)
+}
+
+fn unconcat<N: Number>(target: &N, rhs: &N, base: &N) -> Option<N> {
+    let (lhs, remainder) = target.checked_div_rem(&concat_shift(rhs, base)?)?;
+    (remainder == *rhs).then_some(lhs)
+}
+
+/// Subtraction, as long as the running total never goes below zero.
+#[derive(Debug, Clone, Copy)]
+struct Subtract;
+
+impl<N: Number> Operator<N> for Subtract {
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N> {
+        lhs.checked_sub(rhs)
+    }
+
+    fn undo(&self, target: &N, rhs: &N) -> Undo<N> {
+        target.checked_add(rhs).into()
+    }
+}
+
+impl fmt::Display for Subtract {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("-")
+    }
+}
+
+/// Integer division, rounding down. Several left-hand sides divide down to
+/// the same result, so this has no undo.
+#[derive(Debug, Clone, Copy)]
+struct Divide;
+
+impl<N: Number> Operator<N> for Divide {
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N> {
+        lhs.checked_div_rem(rhs).map(|(quotient, _)| quotient)
+    }
+
+    fn precedence(&self) -> u8 {
+        2
+    }
+}
+
+impl fmt::Display for Divide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("/")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Power;
+
+impl<N: Number> Operator<N> for Power {
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N> {
+        lhs.checked_pow(rhs)
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+}
+
+impl fmt::Display for Power {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("^")
+    }
+}
+
+/// Concatenation of the digits of both sides written in `base`.
+#[derive(Debug, Clone, Copy)]
+struct ConcatIn {
+    base: u64,
+}
+
+impl<N: Number> Operator<N> for ConcatIn {
+    fn apply(&self, lhs: &N, rhs: &N) -> Option<N> {
+        concat(lhs, rhs, &N::from(self.base))
+    }
+
+    fn undo(&self, target: &N, rhs: &N) -> Undo<N> {
+        unconcat(target, rhs, &N::from(self.base)).into()
+    }
+
+    fn precedence(&self) -> u8 {
+        3
+    }
+
+    fn never_shrinks(&self) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for ConcatIn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "||{}", self.base)
+    }
+}
+
+/// The operators to try between the numbers of each calibration.
+struct OperatorSet<N> {
+    operators: Vec<Box<dyn Operator<N>>>,
+}
+
+impl<N> Default for OperatorSet<N> {
+    fn default() -> Self {
+        Self {
+            operators: Vec::new(),
+        }
+    }
+}
+
+impl<N: Number> OperatorSet<N> {
+    fn register(mut self, operator: impl Operator<N> + 'static) -> Self {
+        self.operators.push(Box::new(operator));
+        self
+    }
+
+    /// Part one's operators.
+    fn add_multiply() -> Self {
+        Self::default()
+            .register(Symbol::Add)
+            .register(Symbol::Multiply)
+    }
+
+    /// Part two's operators.
+    fn add_multiply_concat() -> Self {
+        Self::add_multiply().register(Symbol::Concat)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &dyn Operator<N>> {
+        self.operators.iter().map(Box::as_ref)
+    }
+}
+
+impl<N: Number> FromStr for OperatorSet<N> {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',').try_fold(Self::default(), |set, symbol| {
+            let set = match symbol.trim() {
+                "+" => set.register(Symbol::Add),
+                "*" => set.register(Symbol::Multiply),
+                "||" => set.register(Symbol::Concat),
+                "-" => set.register(Subtract),
+                "/" => set.register(Divide),
+                "^" => set.register(Power),
+                other => {
+                    let base = other
+                        .strip_prefix("||")
+                        .and_then(|base| base.parse::<u64>().ok())
+                        .filter(|&base| base >= 2)
+                        .ok_or_else(|| anyhow!("Unknown operator {other:?}."))?;
+                    set.register(ConcatIn { base })
+                }
+            };
+            Ok(set)
+        })
+    }
+}
+
+/// A calibration together with the operators that make it true.
+struct Equation<'n, 'o, N> {
+    test_value: &'n N,
+    nums: &'n [N],
+    operators: Vec<&'o dyn Operator<N>>,
+}
+
+impl<N: Number> fmt::Display for Equation<'_, '_, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((first, rest)) = self.nums.split_first() else {
+            return Ok(());
+        };
+        write!(f, "{} = {first}", self.test_value)?;
+        for (operator, num) in self.operators.iter().zip(rest) {
+            write!(f, " {operator} {num}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether to stop at the first operator assignment that makes an
+/// equation true, or find them all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Solutions {
+    First,
+    All,
+}
+
+/// A search for the operator assignments that make `nums` give a test
+/// value.
+///
+/// Evaluating left to right, this works backwards from the test value,
+/// undoing the last number with each operator in turn and recursing on
+/// what's left, so dead ends are cut off as soon as an operator can't be
+/// undone rather than after every combination has been built. Where an
+/// operator can't be undone, the numbers before it are evaluated forwards
+/// in every way instead. With precedence, every assignment is tried. Any
+/// bracketing is worked out by `Bracketings` rather than searched for.
+struct Search<'n, 'o, N> {
+    nums: &'n [N],
+    operators: &'o OperatorSet<N>,
+    evaluation: Evaluation,
+    which: Solutions,
+    /// Whether to keep the assignments found, or only count them.
+    keep: bool,
+    found: Vec<Vec<&'o dyn Operator<N>>>,
+    n_found: usize,
+}
+
+impl<'n, 'o, N: Number> Search<'n, 'o, N> {
+    fn new(
+        nums: &'n [N],
+        operators: &'o OperatorSet<N>,
+        evaluation: Evaluation,
+        which: Solutions,
+        keep: bool,
+    ) -> Self {
+        Self {
+            nums,
+            operators,
+            evaluation,
+            which,
+            keep,
+            found: Vec::new(),
+            n_found: 0,
         }
-        match sym {
-            Symbol::Add => total += *rhs,
-            Symbol::Multiply => total *= *rhs,
-            Symbol::Concat => {
-                let shift_mul = 10_u64.pow(rhs.ilog10() + 1);
-                total = total * shift_mul + rhs;
+    }
+
+    fn run(mut self, test_value: &N) -> Self {
+        if self.nums.is_empty() {
+            return self;
+        }
+        let mut operators = Vec::with_capacity(self.nums.len() - 1);
+        match self.evaluation {
+            Evaluation::LeftToRight => self.backwards(test_value, self.nums.len(), &mut operators),
+            Evaluation::Precedence => self.every_assignment(test_value, &mut operators),
+            Evaluation::AnyBracketing => {
+                unreachable!("Bracketings are worked out by Bracketings, not searched.")
             }
         }
+        self
+    }
+
+    fn finished(&self) -> bool {
+        self.which == Solutions::First && self.n_found > 0
     }
-    test_value == total
+
+    /// Find the ways the first `len` numbers make `target`. The `trail`
+    /// holds the operators already undone after them, last one first.
+    fn backwards(&mut self, target: &N, len: usize, trail: &mut Vec<&'o dyn Operator<N>>) {
+        if len == 1 {
+            if self.nums[0] == *target {
+                self.record(&[], trail);
+            }
+            return;
+        }
+        let last = &self.nums[len - 1];
+        for operator in self.operators.iter() {
+            if self.finished() {
+                return;
+            }
+            trail.push(operator);
+            match operator.undo(target, last) {
+                Undo::Impossible => (),
+                Undo::Lhs(lhs) => self.backwards(&lhs, len - 1, trail),
+                Undo::Unknown => {
+                    let mut prefix = Vec::with_capacity(len - 2);
+                    let first = self.nums[0].clone();
+                    self.forwards(first, 1, len - 1, &mut prefix, target, trail);
+                }
+            }
+            trail.pop();
+        }
+    }
+
+    /// Evaluate the numbers before index `end` in every way, carrying on
+    /// from `total` at index `idx`, and record the ways for which the last
+    /// operator of the trail then makes `target` with the number at `end`.
+    fn forwards(
+        &mut self,
+        total: N,
+        idx: usize,
+        end: usize,
+        prefix: &mut Vec<&'o dyn Operator<N>>,
+        target: &N,
+        trail: &[&'o dyn Operator<N>],
+    ) {
+        if idx == end {
+            let operator = trail.last().expect("Forwards search follows an operator.");
+            if operator.apply(&total, &self.nums[end]).as_ref() == Some(target) {
+                self.record(prefix, trail);
+            }
+            return;
+        }
+        for operator in self.operators.iter() {
+            if self.finished() {
+                return;
+            }
+            if let Some(total) = operator.apply(&total, &self.nums[idx]) {
+                prefix.push(operator);
+                self.forwards(total, idx + 1, end, prefix, target, trail);
+                prefix.pop();
+            }
+        }
+    }
+
+    /// Try every way of filling in the rest of `prefix`, checking each
+    /// complete assignment from scratch.
+    fn every_assignment(&mut self, target: &N, prefix: &mut Vec<&'o dyn Operator<N>>) {
+        if prefix.len() + 1 == self.nums.len() {
+            if evaluate_with_precedence(self.nums, prefix).as_ref() == Some(target) {
+                self.record(prefix, &[]);
+            }
+            return;
+        }
+        for operator in self.operators.iter() {
+            if self.finished() {
+                return;
+            }
+            prefix.push(operator);
+            self.every_assignment(target, prefix);
+            prefix.pop();
+        }
+    }
+
+    fn record(&mut self, prefix: &[&'o dyn Operator<N>], trail: &[&'o dyn Operator<N>]) {
+        self.n_found += 1;
+        if self.keep {
+            let assignment = prefix.iter().chain(trail.iter().rev()).copied().collect();
+            self.found.push(assignment);
+        }
+    }
+}
+
+/// The choices of `operators` between `nums` that make `test_value`, each
+/// listed left to right. Evaluating left to right, they come out ordered by
+/// their last operator first, as that is the one the search undoes first.
+fn find_assignments<'o, N: Number>(
+    test_value: &N,
+    nums: &[N],
+    operators: &'o OperatorSet<N>,
+    evaluation: Evaluation,
+    which: Solutions,
+) -> Vec<Vec<&'o dyn Operator<N>>> {
+    Search::new(nums, operators, evaluation, which, true)
+        .run(test_value)
+        .found
 }
 
-fn calibrate_all_sequences(
-    calibrations: &[(u64, Vec<u64>)],
-    operators: HashMap<usize, Vec<Vec<Symbol>>>,
-) -> u64 {
-    calibrations
+/// How many choices of `operators` between `nums` make `test_value`,
+/// without building any of them.
+fn count_assignments<N: Number>(
+    test_value: &N,
+    nums: &[N],
+    operators: &OperatorSet<N>,
+    evaluation: Evaluation,
+) -> usize {
+    Search::new(nums, operators, evaluation, Solutions::All, false)
+        .run(test_value)
+        .n_found
+}
+
+/// Whether `nums`, joined by some choice of `operators` and evaluated as
+/// `evaluation` says, can make `test_value`.
+fn calibrate_one_sequence<N: Number>(
+    test_value: &N,
+    nums: &[N],
+    operators: &OperatorSet<N>,
+    evaluation: Evaluation,
+) -> bool {
+    if evaluation == Evaluation::AnyBracketing {
+        let all_operators: Vec<_> = operators.iter().collect();
+        let gaps = vec![all_operators; nums.len().saturating_sub(1)];
+        return Bracketings::new(nums, &gaps, Some(test_value)).makes(test_value);
+    }
+    Search::new(nums, operators, evaluation, Solutions::First, false)
+        .run(test_value)
+        .n_found
+        > 0
+}
+
+/// The value of `nums` joined by `operators`, evaluating tighter binding
+/// operators first and otherwise going left to right.
+fn evaluate_with_precedence<N: Number>(nums: &[N], operators: &[&dyn Operator<N>]) -> Option<N> {
+    fn reduce<N>(values: &mut Vec<N>, pending: &mut Vec<&dyn Operator<N>>) -> Option<()> {
+        let rhs = values.pop()?;
+        let lhs = values.pop()?;
+        values.push(pending.pop()?.apply(&lhs, &rhs)?);
+        Some(())
+    }
+
+    let (first, rest) = nums.split_first()?;
+    let mut values = vec![first.clone()];
+    let mut pending: Vec<&dyn Operator<N>> = Vec::new();
+    for (&operator, num) in operators.iter().zip(rest) {
+        while pending
+            .last()
+            .is_some_and(|top| top.precedence() >= operator.precedence())
+        {
+            reduce(&mut values, &mut pending)?;
+        }
+        pending.push(operator);
+        values.push(num.clone());
+    }
+    while !pending.is_empty() {
+        reduce(&mut values, &mut pending)?;
+    }
+    values.pop()
+}
+
+/// How a value was made from a run of numbers: by `operator` joining the
+/// numbers up to `at` (making `lhs`) and the ones after (making `rhs`).
+struct Split<'o, N> {
+    at: usize,
+    lhs: N,
+    rhs: N,
+    operator: &'o dyn Operator<N>,
+}
+
+/// Every value each run of consecutive numbers can make under any
+/// bracketing, worked out from shorter runs to longer ones so each run's
+/// values are only found once.
+struct Bracketings<'n, 'o, N> {
+    nums: &'n [N],
+    /// The values of each run `(first, last)`.
+    values: HashMap<(usize, usize), RunValues<'o, N>>,
+}
+
+/// Every value a run makes and one way of making it, or `None` for a run
+/// of one number.
+type RunValues<'o, N> = HashMap<N, Option<Split<'o, N>>>;
+
+impl<'n, 'o, N: Number> Bracketings<'n, 'o, N> {
+    /// `gaps` holds the operators allowed between each pair of neighbouring
+    /// numbers. Given a `target`, the run of all the numbers only keeps that
+    /// value, and if every operator never shrinks and there are no zeros,
+    /// values over it can never come back down, so they're dropped too.
+    fn new(nums: &'n [N], gaps: &[Vec<&'o dyn Operator<N>>], target: Option<&N>) -> Self {
+        let growing = gaps.iter().flatten().all(|op| op.never_shrinks())
+            && nums.iter().all(|num| !num.is_zero());
+        let cap = target.filter(|_| growing);
+        let mut values = HashMap::new();
+        for (idx, num) in nums.iter().enumerate() {
+            let mut made = HashMap::with_capacity(1);
+            made.insert(num.clone(), None);
+            values.insert((idx, idx), made);
+        }
+        for len in 2..=nums.len() {
+            for first in 0..=nums.len() - len {
+                let last = first + len - 1;
+                let mut made = HashMap::new();
+                for at in first..last {
+                    for lhs in values[&(first, at)].keys() {
+                        for rhs in values[&(at + 1, last)].keys() {
+                            for &operator in &gaps[at] {
+                                let Some(value) = operator.apply(lhs, rhs) else {
+                                    continue;
+                                };
+                                if cap.is_some_and(|cap| value > *cap)
+                                    || (len == nums.len()
+                                        && target.is_some_and(|target| value != *target))
+                                {
+                                    continue;
+                                }
+                                made.entry(value).or_insert_with(|| {
+                                    Some(Split {
+                                        at,
+                                        lhs: lhs.clone(),
+                                        rhs: rhs.clone(),
+                                        operator,
+                                    })
+                                });
+                            }
+                        }
+                    }
+                }
+                values.insert((first, last), made);
+            }
+        }
+        Self { nums, values }
+    }
+
+    fn makes(&self, target: &N) -> bool {
+        self.nums
+            .len()
+            .checked_sub(1)
+            .is_some_and(|last| self.values[&(0, last)].contains_key(target))
+    }
+
+    /// One bracketed expression of all the numbers that makes `target`.
+    fn expression(&self, target: &N) -> Option<String> {
+        let last = self.nums.len().checked_sub(1)?;
+        self.values[&(0, last)]
+            .contains_key(target)
+            .then(|| self.write_run(0, last, target, false))
+    }
+
+    fn write_run(&self, first: usize, last: usize, value: &N, bracket: bool) -> String {
+        let Some(Split {
+            at,
+            lhs,
+            rhs,
+            operator,
+        }) = &self.values[&(first, last)][value]
+        else {
+            return value.to_string();
+        };
+        let lhs = self.write_run(first, *at, lhs, true);
+        let rhs = self.write_run(at + 1, last, rhs, true);
+        if bracket {
+            format!("({lhs} {operator} {rhs})")
+        } else {
+            format!("{lhs} {operator} {rhs}")
+        }
+    }
+}
+
+/// The sum of the test values that can be made true, which is an error if
+/// it doesn't fit in an `N`.
+fn calibrate_all_sequences<N: Number>(
+    calibrations: &[(N, Vec<N>)],
+    operators: &OperatorSet<N>,
+    evaluation: Evaluation,
+) -> anyhow::Result<N> {
+    let valid: Vec<&N> = calibrations
         .par_iter()
         .filter(|(test_value, nums)| {
-            operators[&nums.len()]
-                .par_iter()
-                .any(|ops| calibrate_one_sequence(*test_value, nums, ops))
+            calibrate_one_sequence(test_value, nums, operators, evaluation)
         })
-        .map(|(test_value, _)| *test_value)
-        .sum()
+        .map(|(test_value, _)| test_value)
+        .collect();
+    valid
+        .into_iter()
+        .try_fold(N::from(0), |sum, test_value| sum.checked_add(test_value))
+        .ok_or_else(|| anyhow!("The sum of the valid test values overflows."))
 }
 
-fn part_one(calibrations: &[(u64, Vec<u64>)]) -> u64 {
-    calibrate_all_sequences(calibrations, simple_operators())
+fn part_one<N: Number>(calibrations: &[(N, Vec<N>)]) -> anyhow::Result<N> {
+    calibrate_all_sequences(
+        calibrations,
+        &OperatorSet::add_multiply(),
+        Evaluation::LeftToRight,
+    )
 }
 
-fn part_two(calibrations: &[(u64, Vec<u64>)]) -> u64 {
-    calibrate_all_sequences(calibrations, complex_operators())
+fn part_two<N: Number>(calibrations: &[(N, Vec<N>)]) -> anyhow::Result<N> {
+    calibrate_all_sequences(
+        calibrations,
+        &OperatorSet::add_multiply_concat(),
+        Evaluation::LeftToRight,
+    )
 }
 
-type Calibrations = Vec<(u64, Vec<u64>)>;
+type Calibrations<N> = Vec<(N, Vec<N>)>;
 
-fn parse_calibrations(input: &str) -> anyhow::Result<Calibrations> {
+fn parse_calibrations<N: Number>(input: &str) -> anyhow::Result<Calibrations<N>> {
     let (input, calibrations) = terminated(
         separated_list1(line_ending, _parse_single_calibration),
         many0(line_ending),
@@ -119,16 +941,32 @@ fn parse_calibrations(input: &str) -> anyhow::Result<Calibrations> {
     Ok(calibrations)
 }
 
-fn _parse_single_calibration(input: &str) -> IResult<&str, (u64, Vec<u64>)> {
+fn _parse_single_calibration<N: Number>(input: &str) -> IResult<&str, (N, Vec<N>)> {
     separated_pair(
-        complete::u64,
+        _parse_number,
         tag(": "),
-        separated_list1(space1, complete::u64),
+        separated_list1(space1, _parse_number),
     )(input)
 }
 
+/// A number, which fails to parse (rather than wrapping) if it's too big
+/// for an `N`.
+fn _parse_number<N: Number>(input: &str) -> IResult<&str, N> {
+    map_res(digit1, str::parse::<N>)(input)
+}
+
 #[cfg(test)]
 mod test {
+    use num_bigint::BigUint;
+
+    use super::Evaluation::{self, AnyBracketing, LeftToRight, Precedence};
+    use super::{
+        calibrate_all_sequences, calibrate_one_sequence, concat, count_assignments,
+        evaluate_with_precedence, find_assignments, list_equations, parse_calibrations, unconcat,
+        Bracketings, ConcatIn, Divide, Equation, Operator, OperatorSet, Solutions, Subtract,
+        Symbol, Undo,
+    };
+
     const SAMPLE_INPUT: &str = "\
 190: 10 19
 3267: 81 40 27
@@ -143,24 +981,313 @@ mod test {
 
     #[test]
     fn parse_sample_calibrations() -> anyhow::Result<()> {
-        let calibrations = super::parse_calibrations(SAMPLE_INPUT)?;
+        let calibrations = parse_calibrations::<u64>(SAMPLE_INPUT)?;
         assert_eq!(calibrations.first().unwrap(), &(190, vec![10, 19]));
         Ok(())
     }
 
     #[test]
     pub fn solve_sample_part_one() -> anyhow::Result<()> {
-        let calibrations = super::parse_calibrations(SAMPLE_INPUT)?;
-        let sum_of_valid_calibrations = super::part_one(&calibrations);
+        let calibrations = parse_calibrations::<u64>(SAMPLE_INPUT)?;
+        let sum_of_valid_calibrations = super::part_one(&calibrations)?;
         assert_eq!(sum_of_valid_calibrations, 3749);
         Ok(())
     }
 
     #[test]
     pub fn solve_sample_part_two() -> anyhow::Result<()> {
-        let calibrations = super::parse_calibrations(SAMPLE_INPUT)?;
-        let sum_of_valid_calibrations = super::part_two(&calibrations);
+        let calibrations = parse_calibrations::<u64>(SAMPLE_INPUT)?;
+        let sum_of_valid_calibrations = super::part_two(&calibrations)?;
         assert_eq!(sum_of_valid_calibrations, 11387);
         Ok(())
     }
+
+    fn undo(operator: &dyn Operator<u64>, target: u64, rhs: u64) -> Undo<u64> {
+        operator.undo(&target, &rhs)
+    }
+
+    #[test]
+    fn undo_operators() {
+        assert_eq!(undo(&Symbol::Add, 3267, 27), Undo::Lhs(3240));
+        assert_eq!(undo(&Symbol::Add, 20, 27), Undo::Impossible);
+        assert_eq!(undo(&Symbol::Multiply, 3267, 27), Undo::Lhs(121));
+        assert_eq!(undo(&Symbol::Multiply, 3268, 27), Undo::Impossible);
+        assert_eq!(undo(&Symbol::Multiply, 0, 0), Undo::Unknown);
+        assert_eq!(undo(&Symbol::Concat, 156, 6), Undo::Lhs(15));
+        assert_eq!(undo(&Symbol::Concat, 156, 56), Undo::Lhs(1));
+        assert_eq!(undo(&Symbol::Concat, 156, 7), Undo::Impossible);
+        assert_eq!(undo(&ConcatIn { base: 2 }, 0b1011, 0b11), Undo::Lhs(0b10));
+        assert_eq!(undo(&Subtract, 5, 3), Undo::Lhs(8));
+        assert_eq!(undo(&Divide, 5, 3), Undo::Unknown);
+    }
+
+    #[test]
+    fn calibrate_long_sequences() {
+        // Far more numbers than an exhaustive search over operators allows.
+        let nums = [1; 40];
+        let all = OperatorSet::add_multiply_concat();
+        assert!(calibrate_one_sequence(&40, &nums, &all, LeftToRight));
+        assert!(calibrate_one_sequence(&1, &nums, &all, LeftToRight));
+        assert!(calibrate_one_sequence(&(11 + 38), &nums, &all, LeftToRight));
+        // Every combination of 2s is even, and only subtracting ever undoes an
+        // odd target, so the search follows one chain down and gives up.
+        assert!(!calibrate_one_sequence(
+            &(2 * 40 + 1),
+            &[2; 40],
+            &all,
+            LeftToRight
+        ));
+    }
+
+    fn equations(test_value: u64, nums: &[u64], operators: &str, which: Solutions) -> Vec<String> {
+        equations_by(test_value, nums, operators, LeftToRight, which)
+    }
+
+    fn equations_by(
+        test_value: u64,
+        nums: &[u64],
+        operators: &str,
+        evaluation: Evaluation,
+        which: Solutions,
+    ) -> Vec<String> {
+        let operators: OperatorSet<u64> = operators.parse().unwrap();
+        find_assignments(&test_value, nums, &operators, evaluation, which)
+            .into_iter()
+            .map(|operators| {
+                Equation {
+                    test_value: &test_value,
+                    nums,
+                    operators,
+                }
+                .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn find_operator_assignments() {
+        assert_eq!(
+            equations(3267, &[81, 40, 27], "+,*", Solutions::All),
+            ["3267 = 81 * 40 + 27", "3267 = 81 + 40 * 27"]
+        );
+        assert_eq!(
+            equations(3267, &[81, 40, 27], "+,*", Solutions::First),
+            ["3267 = 81 * 40 + 27"]
+        );
+        assert!(equations(83, &[17, 5], "+,*", Solutions::All).is_empty());
+        assert_eq!(
+            equations(7290, &[6, 8, 6, 15], "+,*,||", Solutions::All),
+            ["7290 = 6 * 8 || 6 * 15"]
+        );
+    }
+
+    #[test]
+    fn count_operator_assignments() {
+        let add_multiply = OperatorSet::add_multiply();
+        assert_eq!(
+            count_assignments(&3267, &[81, 40, 27], &add_multiply, LeftToRight),
+            2
+        );
+        assert_eq!(
+            count_assignments(&83, &[17, 5], &add_multiply, LeftToRight),
+            0
+        );
+        // (1 + 1) * 1 * 3, 1 * 1 + 1 * 3 and 1 + 1 + 1 + 3.
+        assert_eq!(
+            count_assignments(&6, &[1, 1, 1, 3], &add_multiply, LeftToRight),
+            3
+        );
+    }
+
+    #[test]
+    fn user_defined_operators() {
+        assert_eq!(
+            equations(7, &[10, 3], "+,-", Solutions::All),
+            ["7 = 10 - 3"]
+        );
+        // Neither / nor ^ can be undone, so these are found going forwards.
+        assert_eq!(
+            equations(3, &[10, 3, 1], "/,+", Solutions::All),
+            ["3 = 10 / 3 / 1"]
+        );
+        assert_eq!(
+            equations(64, &[2, 3, 2], "^,*", Solutions::All),
+            ["64 = 2 ^ 3 ^ 2"]
+        );
+        assert_eq!(
+            equations(0b1011, &[0b10, 0b11], "||2", Solutions::All),
+            ["11 = 2 ||2 3"]
+        );
+        assert!("+,%".parse::<OperatorSet<u64>>().is_err());
+        assert!("||1".parse::<OperatorSet<u64>>().is_err());
+    }
+
+    #[test]
+    fn multiply_by_zero() {
+        // Anything times zero is zero, whatever came before.
+        let add_multiply = OperatorSet::add_multiply();
+        assert_eq!(
+            count_assignments(&0, &[5, 7, 0], &add_multiply, LeftToRight),
+            2
+        );
+        assert_eq!(
+            count_assignments(&0, &[5, 0, 0], &add_multiply, LeftToRight),
+            3
+        );
+    }
+
+    #[test]
+    fn display_equations() {
+        assert_eq!(
+            equations(156, &[15, 6], "||", Solutions::First),
+            ["156 = 15 || 6"]
+        );
+    }
+
+    #[test]
+    fn concatenate_zeros() {
+        assert_eq!(concat(&5, &0, &10), Some(50_u64));
+        assert_eq!(concat(&0, &7, &10), Some(7_u64));
+        assert_eq!(unconcat(&50, &0, &10), Some(5_u64));
+        assert_eq!(unconcat(&5, &0, &10), None);
+        assert_eq!(undo(&Symbol::Concat, 0, 0), Undo::Lhs(0));
+        let all = OperatorSet::add_multiply_concat();
+        assert!(calibrate_one_sequence(
+            &1000,
+            &[1, 0, 0, 0],
+            &all,
+            LeftToRight
+        ));
+    }
+
+    #[test]
+    fn overflow_is_not_a_panic() -> anyhow::Result<()> {
+        let all = OperatorSet::add_multiply_concat();
+        let max = u64::MAX;
+        assert!(calibrate_one_sequence(&max, &[max, 1], &all, LeftToRight));
+        assert!(!calibrate_one_sequence(
+            &max,
+            &[max / 2, 3],
+            &all,
+            LeftToRight
+        ));
+        assert_eq!(concat(&max, &1, &10), None);
+        assert!(parse_calibrations::<u64>("1: 99999999999999999999 1").is_err());
+        let input = format!("{max}: {max}\n{max}: {max}");
+        assert!(super::part_one(&parse_calibrations::<u64>(&input)?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn solve_with_big_integers() -> anyhow::Result<()> {
+        let input = "\
+123456789012345678901234567890: 1234567890123 45678901234567890
+36893488147419103230: 18446744073709551615 2
+";
+        let calibrations = parse_calibrations::<BigUint>(input)?;
+        let expected: BigUint = "36893488147419103230".parse()?;
+        assert_eq!(super::part_one(&calibrations)?, expected);
+        let expected = expected + "123456789012345678901234567890".parse::<BigUint>()?;
+        assert_eq!(super::part_two(&calibrations)?, expected);
+        // The sample gives the same answers whatever the precision.
+        let sample = parse_calibrations::<BigUint>(SAMPLE_INPUT)?;
+        assert_eq!(super::part_two(&sample)?, BigUint::from(11387_u64));
+        Ok(())
+    }
+
+    #[test]
+    fn evaluate_operators_by_precedence() {
+        let (add, multiply): (&dyn Operator<u64>, &dyn Operator<u64>) =
+            (&Symbol::Add, &Symbol::Multiply);
+        assert_eq!(
+            evaluate_with_precedence(&[81, 40, 27], &[add, multiply]),
+            Some(81 + 40 * 27)
+        );
+        assert_eq!(
+            evaluate_with_precedence(&[2, 3, 4, 5], &[multiply, add, multiply]),
+            Some(26)
+        );
+        assert_eq!(
+            evaluate_with_precedence(&[10, 3, 2, 4], &[&Subtract, add, multiply]),
+            Some(15)
+        );
+        assert_eq!(evaluate_with_precedence(&[7], &[]), Some(7));
+    }
+
+    #[test]
+    fn solve_with_precedence() {
+        assert_eq!(
+            equations_by(3267, &[81, 40, 27], "+,*", Precedence, Solutions::All),
+            ["3267 = 81 * 40 + 27"]
+        );
+        assert_eq!(
+            equations_by(1161, &[81, 40, 27], "+,*", Precedence, Solutions::All),
+            ["1161 = 81 + 40 * 27"]
+        );
+        let add_multiply = OperatorSet::add_multiply();
+        assert!(!calibrate_one_sequence(
+            &292,
+            &[11, 6, 16, 20],
+            &add_multiply,
+            Precedence
+        ));
+        assert!(calibrate_one_sequence(
+            &(11 + 6 * 16 + 20),
+            &[11, 6, 16, 20],
+            &add_multiply,
+            Precedence
+        ));
+    }
+
+    #[test]
+    fn solve_with_any_bracketing() {
+        let add_multiply = OperatorSet::add_multiply();
+        // 3 * (4 + 2) isn't reachable left to right or by precedence.
+        for (evaluation, expected) in [
+            (LeftToRight, false),
+            (Precedence, false),
+            (AnyBracketing, true),
+        ] {
+            assert_eq!(
+                calibrate_one_sequence(&18, &[3, 4, 2], &add_multiply, evaluation),
+                expected,
+                "{evaluation:?}"
+            );
+        }
+        let gaps = vec![vec![&Symbol::Add as &dyn Operator<u64>, &Symbol::Multiply]; 3];
+        let bracketings = Bracketings::new(&[2, 3, 4, 5], &gaps, None);
+        assert_eq!(bracketings.expression(&70).unwrap(), "2 * ((3 + 4) * 5)");
+        assert_eq!(bracketings.expression(&19).unwrap(), "2 + ((3 * 4) + 5)");
+        assert_eq!(bracketings.expression(&1000), None);
+    }
+
+    #[test]
+    fn bracketings_agree_with_the_sample() -> anyhow::Result<()> {
+        // Any bracketing includes the left to right one, so it finds at least
+        // as many equations.
+        let calibrations = parse_calibrations::<u64>(SAMPLE_INPUT)?;
+        let operators = OperatorSet::add_multiply();
+        let left = calibrate_all_sequences(&calibrations, &operators, LeftToRight)?;
+        let any = calibrate_all_sequences(&calibrations, &operators, AnyBracketing)?;
+        assert_eq!(left, 3749);
+        assert!(any >= left);
+        Ok(())
+    }
+
+    #[test]
+    fn list_bracketed_equations() -> anyhow::Result<()> {
+        let listed =
+            list_equations::<u64>("18: 3 4 2", "+,*", (Solutions::First, false, AnyBracketing))?;
+        assert_eq!(
+            listed,
+            "18 = 3 * (4 + 2)\n1 of 1 equations can be made true"
+        );
+        for settings in [
+            (Solutions::All, false, AnyBracketing),
+            (Solutions::First, true, AnyBracketing),
+        ] {
+            assert!(list_equations::<u64>("18: 3 4 2", "+,*", settings).is_err());
+        }
+        Ok(())
+    }
 }
@@ -1,83 +1,325 @@
 use std::cmp::Ordering;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::str::FromStr;
 
+use anyhow::anyhow;
 use glam::IVec2;
-use itertools::Itertools;
+use itertools::{Either, Itertools};
 
 use crate::util::Answer;
 
 pub fn solve(input: &str) -> anyhow::Result<String> {
     let grid: Grid = input.parse()?;
 
-    let p1 = grid.count_antinode_positions(AntinodeMethod::Simple);
+    let p1 = grid.count_antinode_positions(&AntinodeRule::simple());
     assert_eq!(p1, 344, "Part one is not correct.");
 
-    let p2 = grid.count_antinode_positions(AntinodeMethod::Resonant);
+    let p2 = grid.count_antinode_positions(&AntinodeRule::resonant());
     assert_eq!(p2, 1182, "Part two is not correct.");
 
     Answer::first(8, p1).second(p2).report()
 }
 
+/// Options build an antinode rule, starting from part one's unless
+/// `--resonant` starts from part two's. `--harmonics <n|a..|a..=b>` sets
+/// how many steps beyond each antenna count, `--between` adds the points
+/// between each pair, and `--reduce` steps by the pair's difference divided
+/// by its gcd. `--coordinates <path>` reads antennas from a file of
+/// `frequency,row,col` lines instead of the map, and `--region
+/// <none|rect:..|poly:..>` sets where antinodes can be. Then `--map` draws
+/// the antinodes over the map, `--counts` lists how many antinodes each
+/// frequency makes, `--sources` lists the antenna pairs behind every
+/// antinode, and `--stream` writes every antinode out as it's found, once
+/// for each pair that makes it.
+pub fn explore(
+    input: &str,
+    options: &[String],
+    out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    let mut grid: Grid = input.parse()?;
+    let mut region = None;
+    let mut rule = AntinodeRule::simple();
+    let mut harmonics = None;
+    let (mut show_map, mut show_counts, mut show_sources) = (false, false, false);
+    let mut stream = false;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--resonant" => {
+                rule = AntinodeRule::resonant()
+                    .with_between(rule.between)
+                    .with_reduce(rule.reduce)
+            }
+            "--harmonics" => {
+                let value = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--harmonics needs a value such as 1, 0.. or 1..=3"))?;
+                harmonics = Some(value.parse()?);
+            }
+            "--between" => rule = rule.with_between(true),
+            "--reduce" => rule = rule.with_reduce(true),
+            "--coordinates" => {
+                let path = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--coordinates needs a file path"))?;
+                grid = Grid::from_coordinates(&std::fs::read_to_string(path)?)?;
+            }
+            "--region" => {
+                let spec = options
+                    .next()
+                    .ok_or_else(|| anyhow!("--region needs none, rect:r,c,r,c or poly:r,c;..."))?;
+                region = Some(spec.parse()?);
+            }
+            "--map" => show_map = true,
+            "--counts" => show_counts = true,
+            "--sources" => show_sources = true,
+            "--stream" => stream = true,
+            other => return Err(anyhow!("Unknown option for day 8: {other}")),
+        }
+    }
+    // Given in any order, `--harmonics` wins over the preset's.
+    if let Some(harmonics) = harmonics {
+        rule = rule.with_harmonics(harmonics);
+    }
+    if let Some(region) = region {
+        grid = grid.with_region(region);
+    }
+    grid.check_finite(&rule)?;
+    let mut write_antinode = |(antinode, source): (Position, Source)| {
+        writeln!(out, "{antinode}: {source}")?;
+        anyhow::Ok((antinode, source))
+    };
+    if stream && !(show_map || show_counts || show_sources) {
+        // Nothing needs the antinodes afterwards, so don't keep them.
+        let mut n_streamed = 0_usize;
+        for antinode in grid.antinodes(&rule) {
+            write_antinode(antinode)?;
+            n_streamed += 1;
+        }
+        return Ok(format!(
+            "{n_streamed} antinodes, counting each pair, with {rule:?}"
+        ));
+    }
+    let report = if stream {
+        grid.antinodes(&rule)
+            .map(write_antinode)
+            .collect::<anyhow::Result<_>>()?
+    } else {
+        grid.antinode_report(&rule)
+    };
+    let mut lines = Vec::new();
+    if show_map {
+        lines.push(grid.render(&report)?);
+    }
+    if show_counts {
+        for (frequency, count) in report.counts_by_frequency() {
+            lines.push(format!("{frequency}: {count}"));
+        }
+    }
+    if show_sources {
+        for (position, sources) in &report.sources {
+            let pairs = sources.iter().map(Source::to_string).join(", ");
+            lines.push(format!("{position}: {pairs}"));
+        }
+    }
+    lines.push(format!("{} antinodes with {rule:?}", report.sources.len()));
+    Ok(lines.join("\n"))
+}
+
 #[derive(Clone, Debug)]
 struct Grid {
-    height: usize,
-    width: usize,
+    /// Where antinodes can be. Antennas can be outside it.
+    region: Region,
     antennas: HashMap<char, HashSet<Position>>,
 }
 
 impl Grid {
+    /// Antennas from a list of `frequency,row,col` lines, which has no
+    /// edges of its own so starts out unbounded.
+    fn from_coordinates(input: &str) -> anyhow::Result<Self> {
+        let mut antennas: HashMap<char, HashSet<Position>> = HashMap::new();
+        for (line_idx, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let bad_line = || anyhow!("Line {} isn't frequency,row,col: {line:?}", line_idx + 1);
+            let [frequency, row, col] = line.split(',').collect::<Vec<_>>()[..] else {
+                return Err(bad_line());
+            };
+            let mut chars = frequency.trim().chars();
+            let (Some(frequency), None) = (chars.next(), chars.next()) else {
+                return Err(bad_line());
+            };
+            let row = row.trim().parse().map_err(|_| bad_line())?;
+            let col = col.trim().parse().map_err(|_| bad_line())?;
+            antennas
+                .entry(frequency)
+                .or_default()
+                .insert(Position::new(row, col));
+        }
+        Ok(Self {
+            region: Region::Unbounded,
+            antennas,
+        })
+    }
+
+    fn with_region(self, region: Region) -> Self {
+        Self { region, ..self }
+    }
+
     fn antenna_positions(&self, antenna: char) -> Option<&HashSet<Position>> {
         self.antennas.get(&antenna)
     }
 
     fn in_bounds(&self, position: &Position) -> bool {
-        let row = position.row() as usize;
-        let col = position.col() as usize;
-        (0..self.height).contains(&row) && (0..self.width).contains(&col)
+        self.region.contains(position)
     }
 
-    fn simple_antinode_positions_for_antenna(&self, antenna: char) -> Option<HashSet<Position>> {
+    fn antinode_positions_for_antenna(
+        &self,
+        antenna: char,
+        rule: &AntinodeRule,
+    ) -> Option<HashSet<Position>> {
         let antennas = self.antenna_positions(antenna)?;
         let antinodes = antennas
             .iter()
             .tuple_combinations()
-            .flat_map(|(first, second)| {
-                let diff = second - first;
-                [first - diff, second + diff]
-            })
-            .filter(|position| self.in_bounds(position))
+            .flat_map(|(first, second)| self.pair_antinodes(*first, *second, rule))
             .collect();
         Some(antinodes)
     }
 
-    fn resonant_antinode_positions_for_antenna(&self, antenna: char) -> Option<HashSet<Position>> {
-        let antennas = self.antenna_positions(antenna)?;
-        let antinodes = antennas
-            .iter()
-            .tuple_combinations()
-            .flat_map(|(first, second)| {
-                let diff = second - first;
-                let from_first = std::iter::successors(Some(*first), move |pos| {
-                    let next = pos - diff;
-                    self.in_bounds(&next).then_some(next)
-                });
-                let from_second = std::iter::successors(Some(*second), move |pos| {
-                    let next = pos + diff;
-                    self.in_bounds(&next).then_some(next)
+    /// The antinodes `rule` gives for one pair of antennas, that are in the
+    /// region.
+    fn pair_antinodes<'g>(
+        &'g self,
+        first: Position,
+        second: Position,
+        rule: &'g AntinodeRule,
+    ) -> impl Iterator<Item = Position> + 'g {
+        let diff = &second - &first;
+        let divisor = gcd(diff.x, diff.y);
+        // The smallest step from one antenna to the other that still lands
+        // on grid points.
+        let unit = diff / divisor;
+        let step = if rule.reduce { unit } else { diff };
+        let between_steps = if rule.reduce {
+            Either::Left(1..divisor)
+        } else {
+            // Harmonic `n` is `n` steps beyond one antenna and `n + 1` beyond
+            // the other. Between them, the points splitting the pair in the
+            // same ratio are `n / (2n + 1)` of the way across from either end.
+            let ratio_steps = (rule.harmonics.from.max(1)..)
+                .take_while(|&n| rule.harmonics.to.is_none_or(|to| n <= to))
+                .map(i64::from)
+                .take_while(move |&n| 2 * n < i64::from(divisor))
+                .filter(move |&n| i64::from(divisor) % (2 * n + 1) == 0)
+                .flat_map(move |n| {
+                    let part = i64::from(divisor) / (2 * n + 1);
+                    [n * part, (n + 1) * part]
+                })
+                .map(|k| k as i32);
+            Either::Right(ratio_steps)
+        };
+        let between = between_steps
+            .filter(|_| rule.between)
+            .filter_map(move |k| first.checked_step(unit, k));
+        let outwards =
+            [(second, step), (first, -step)]
+                .into_iter()
+                .flat_map(move |(antenna, step)| {
+                    // Only walk the harmonics that land in the region's
+                    // bounding box, which may start some way out when the
+                    // antenna itself is outside the region.
+                    let from = i64::from(rule.harmonics.from);
+                    let to = rule.harmonics.to.map_or(i64::MAX, i64::from);
+                    let (from, to) = match self.region.steps_in_bounding_box(antenna, step) {
+                        Some((first, last)) => (from.max(first), to.min(last)),
+                        None if self.region == Region::Unbounded => (from, to),
+                        // Never in the box, so an empty range.
+                        None => (1, 0),
+                    };
+                    (from..=to)
+                        .map_while(move |n| antenna.checked_step(step, i32::try_from(n).ok()?))
                 });
-                from_first.chain(from_second)
+        between
+            .chain(outwards)
+            .filter(|position| self.in_bounds(position))
+    }
+
+    /// Every antinode and the pair of antennas behind it, one at a time, so
+    /// nothing the size of the region is ever built. A position is produced
+    /// once for every pair that makes it.
+    ///
+    /// With an unbounded region and no last harmonic this never ends.
+    fn antinodes<'g>(
+        &'g self,
+        rule: &'g AntinodeRule,
+    ) -> impl Iterator<Item = (Position, Source)> + 'g {
+        self.antennas
+            .iter()
+            .flat_map(move |(&frequency, antennas)| {
+                antennas
+                    .iter()
+                    .tuple_combinations()
+                    .flat_map(move |(first, second)| {
+                        let pair = (*first.min(second), *first.max(second));
+                        self.pair_antinodes(*first, *second, rule)
+                            .map(move |antinode| (antinode, Source { frequency, pair }))
+                    })
             })
+    }
+
+    /// An error if enumerating the antinodes of `rule` would never end.
+    fn check_finite(&self, rule: &AntinodeRule) -> anyhow::Result<()> {
+        if self.region == Region::Unbounded && rule.harmonics.to.is_none() {
+            return Err(anyhow!(
+                "An unbounded region needs harmonics with a last step, like 0..=10."
+            ));
+        }
+        Ok(())
+    }
+
+    /// Every antinode, with the antenna pairs that made it.
+    fn antinode_report(&self, rule: &AntinodeRule) -> AntinodeReport {
+        self.antinodes(rule).collect()
+    }
+
+    /// Draw the region's bounding box as the puzzle does, with a `#` on
+    /// every antinode that isn't hidden under an antenna, and spaces for
+    /// anything outside the region.
+    fn render(&self, report: &AntinodeReport) -> anyhow::Result<String> {
+        let Some((top_left, bottom_right)) = self.region.bounding_box() else {
+            return Err(anyhow!("An unbounded region can't be drawn."));
+        };
+        let antennas: HashMap<Position, char> = self
+            .antennas
+            .iter()
+            .flat_map(|(&frequency, positions)| positions.iter().map(move |&p| (p, frequency)))
             .collect();
-        Some(antinodes)
+        let map = (top_left.row()..=bottom_right.row())
+            .map(|row| {
+                (top_left.col()..=bottom_right.col())
+                    .map(|col| {
+                        let position = Position::new(row, col);
+                        match antennas.get(&position) {
+                            _ if !self.in_bounds(&position) => ' ',
+                            Some(&frequency) => frequency,
+                            None if report.sources.contains_key(&position) => '#',
+                            None => '.',
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n");
+        Ok(map)
     }
 
-    fn count_antinode_positions(&self, method: AntinodeMethod) -> usize {
+    fn count_antinode_positions(&self, rule: &AntinodeRule) -> usize {
         self.antennas
             .keys()
-            .filter_map(|&a| match method {
-                AntinodeMethod::Simple => self.simple_antinode_positions_for_antenna(a),
-                AntinodeMethod::Resonant => self.resonant_antinode_positions_for_antenna(a),
-            })
+            .filter_map(|&a| self.antinode_positions_for_antenna(a, rule))
             .reduce(|mut acc, next| {
                 acc.extend(next);
                 acc
@@ -87,9 +329,323 @@ impl Grid {
     }
 }
 
-enum AntinodeMethod {
-    Simple,
-    Resonant,
+/// Where antinodes can be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Region {
+    /// Every position from `top_left` to `bottom_right`, inclusive.
+    Rectangle {
+        top_left: Position,
+        bottom_right: Position,
+    },
+    /// Every position inside or on the edge of the polygon with these
+    /// corners, in order.
+    Polygon(Vec<Position>),
+    Unbounded,
+}
+
+impl Region {
+    fn contains(&self, position: &Position) -> bool {
+        match self {
+            Region::Rectangle { .. } => self.in_bounding_box(position),
+            Region::Polygon(corners) => polygon_contains(corners, position),
+            Region::Unbounded => true,
+        }
+    }
+
+    /// The smallest rectangle holding the region, as its top-left and
+    /// bottom-right corners.
+    fn bounding_box(&self) -> Option<(Position, Position)> {
+        match self {
+            Region::Rectangle {
+                top_left,
+                bottom_right,
+            } => Some((*top_left, *bottom_right)),
+            Region::Polygon(corners) => {
+                let (min_row, max_row) =
+                    corners.iter().map(Position::row).minmax().into_option()?;
+                let (min_col, max_col) =
+                    corners.iter().map(Position::col).minmax().into_option()?;
+                Some((
+                    Position::new(min_row, min_col),
+                    Position::new(max_row, max_col),
+                ))
+            }
+            Region::Unbounded => None,
+        }
+    }
+
+    /// The first and last `n` from 0 up for which `start + step * n` is in
+    /// the bounding box, or `None` if there are none or there is no box.
+    fn steps_in_bounding_box(&self, start: Position, step: IVec2) -> Option<(i64, i64)> {
+        let (top_left, bottom_right) = self.bounding_box()?;
+        let (mut first, mut last) = (0_i64, i64::MAX);
+        let axes = [
+            (start.row(), step.x, top_left.row(), bottom_right.row()),
+            (start.col(), step.y, top_left.col(), bottom_right.col()),
+        ];
+        for (start, step, low, high) in axes {
+            let (start, step, low, high) = (
+                i64::from(start),
+                i64::from(step),
+                i64::from(low),
+                i64::from(high),
+            );
+            match step.signum() {
+                0 if !(low..=high).contains(&start) => return None,
+                0 => {}
+                1 => {
+                    first = first.max(ceil_div(low - start, step));
+                    last = last.min((high - start).div_euclid(step));
+                }
+                _ => {
+                    first = first.max(ceil_div(start - high, -step));
+                    last = last.min((start - low).div_euclid(-step));
+                }
+            }
+        }
+        (first <= last).then_some((first, last))
+    }
+
+    fn in_bounding_box(&self, position: &Position) -> bool {
+        self.bounding_box().is_none_or(|(top_left, bottom_right)| {
+            (top_left.row()..=bottom_right.row()).contains(&position.row())
+                && (top_left.col()..=bottom_right.col()).contains(&position.col())
+        })
+    }
+}
+
+impl FromStr for Region {
+    type Err = anyhow::Error;
+
+    /// `none`, `rect:<row>,<col>,<row>,<col>` for the top-left and
+    /// bottom-right corners, or `poly:<row>,<col>;<row>,<col>;...` for a
+    /// polygon's corners in order.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad_region =
+            || anyhow!("Bad region {s:?}, expected none, rect:r,c,r,c or poly:r,c;r,c;...");
+        let parse_position = |text: &str| -> anyhow::Result<Position> {
+            let (row, col) = text.split_once(',').ok_or_else(bad_region)?;
+            Ok(Position::new(row.trim().parse()?, col.trim().parse()?))
+        };
+        if s == "none" {
+            return Ok(Region::Unbounded);
+        }
+        if let Some(corners) = s.strip_prefix("rect:") {
+            let numbers: Vec<i32> = corners
+                .split(',')
+                .map(|n| n.trim().parse())
+                .collect::<Result<_, _>>()?;
+            let [top, left, bottom, right] = numbers[..] else {
+                return Err(bad_region());
+            };
+            return Ok(Region::Rectangle {
+                top_left: Position::new(top, left),
+                bottom_right: Position::new(bottom, right),
+            });
+        }
+        if let Some(corners) = s.strip_prefix("poly:") {
+            let corners = corners
+                .split(';')
+                .map(parse_position)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if corners.len() < 3 {
+                return Err(anyhow!("A polygon needs at least three corners."));
+            }
+            return Ok(Region::Polygon(corners));
+        }
+        Err(bad_region())
+    }
+}
+
+/// `numerator / denominator` rounded up, for a positive `denominator`.
+fn ceil_div(numerator: i64, denominator: i64) -> i64 {
+    -(-numerator).div_euclid(denominator)
+}
+
+/// Whether `position` is inside or on the edge of the polygon with these
+/// corners, by counting how many edges a ray heading left from it crosses.
+fn polygon_contains(corners: &[Position], position: &Position) -> bool {
+    let (row, col) = (i64::from(position.row()), i64::from(position.col()));
+    let mut inside = false;
+    for (a, b) in corners.iter().circular_tuple_windows() {
+        let (a_row, a_col) = (i64::from(a.row()), i64::from(a.col()));
+        let (b_row, b_col) = (i64::from(b.row()), i64::from(b.col()));
+        let cross = (b_row - a_row) * (col - a_col) - (b_col - a_col) * (row - a_row);
+        let on_edge = cross == 0
+            && (a_row.min(b_row)..=a_row.max(b_row)).contains(&row)
+            && (a_col.min(b_col)..=a_col.max(b_col)).contains(&col);
+        if on_edge {
+            return true;
+        }
+        if (a_row > row) != (b_row > row) {
+            // The cross product is the edge's rise times how far the position
+            // is right of where the edge meets this row, so when they have
+            // the same sign the edge is to the left of the position.
+            if (cross > 0) == (b_row > a_row) {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// One pair of same-frequency antennas behind an antinode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Source {
+    frequency: char,
+    /// The antennas' positions, in order.
+    pair: (Position, Position),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (first, second) = self.pair;
+        write!(f, "{} from {first} and {second}", self.frequency)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AntinodeReport {
+    sources: BTreeMap<Position, BTreeSet<Source>>,
+}
+
+impl FromIterator<(Position, Source)> for AntinodeReport {
+    fn from_iter<I: IntoIterator<Item = (Position, Source)>>(antinodes: I) -> Self {
+        let mut sources: BTreeMap<Position, BTreeSet<Source>> = BTreeMap::new();
+        for (antinode, source) in antinodes {
+            sources.entry(antinode).or_default().insert(source);
+        }
+        Self { sources }
+    }
+}
+
+impl AntinodeReport {
+    fn frequencies_at(&self, position: &Position) -> BTreeSet<char> {
+        self.sources
+            .get(position)
+            .into_iter()
+            .flatten()
+            .map(|source| source.frequency)
+            .collect()
+    }
+
+    /// How many antinodes each frequency makes. An antinode made by several
+    /// frequencies counts towards each of them.
+    fn counts_by_frequency(&self) -> BTreeMap<char, usize> {
+        let mut counts = BTreeMap::new();
+        for position in self.sources.keys() {
+            for frequency in self.frequencies_at(position) {
+                *counts.entry(frequency).or_default() += 1;
+            }
+        }
+        counts
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Which points on the line through a pair of same-frequency antennas are
+/// antinodes.
+///
+/// Points are counted in steps of the difference between the antennas, or
+/// with `reduce`, of that difference divided by its gcd, which is the
+/// smallest step that still lands on grid points. Going out beyond each
+/// antenna, the points `n` steps away for each `n` in `harmonics` are
+/// antinodes (where 0 is the antenna itself). `between` adds points strictly
+/// between the antennas: with `reduce` every one on the grid, and without
+/// it those splitting the pair in the ratios the harmonics have outside it,
+/// so harmonic 1's 2:1 points are a third and two thirds of the way across.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AntinodeRule {
+    harmonics: Harmonics,
+    between: bool,
+    reduce: bool,
+}
+
+impl AntinodeRule {
+    /// Part one's rule: just the point twice as far from one antenna as
+    /// from the other, beyond each of them.
+    fn simple() -> Self {
+        Self {
+            harmonics: Harmonics {
+                from: 1,
+                to: Some(1),
+            },
+            between: false,
+            reduce: false,
+        }
+    }
+
+    /// Part two's rule: every step out from each antenna, including the
+    /// antennas themselves.
+    fn resonant() -> Self {
+        Self {
+            harmonics: Harmonics { from: 0, to: None },
+            between: false,
+            reduce: false,
+        }
+    }
+
+    fn with_harmonics(self, harmonics: Harmonics) -> Self {
+        Self { harmonics, ..self }
+    }
+
+    fn with_between(self, between: bool) -> Self {
+        Self { between, ..self }
+    }
+
+    fn with_reduce(self, reduce: bool) -> Self {
+        Self { reduce, ..self }
+    }
+}
+
+/// The steps out from an antenna that count, from `from` up to and
+/// including `to`, or as far as the grid goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Harmonics {
+    from: u32,
+    to: Option<u32>,
+}
+
+impl FromStr for Harmonics {
+    type Err = anyhow::Error;
+
+    /// Either one step such as `1`, an open range such as `0..`, or an
+    /// inclusive range such as `1..=3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| {
+            n.parse::<u32>()
+                .map_err(|e| anyhow!("Bad harmonics {s:?}: {e}"))
+        };
+        match s.split_once("..") {
+            None => {
+                let n = parse(s)?;
+                Ok(Self {
+                    from: n,
+                    to: Some(n),
+                })
+            }
+            Some((from, "")) => Ok(Self {
+                from: parse(from)?,
+                to: None,
+            }),
+            Some((from, to)) => {
+                let to = to.strip_prefix('=').ok_or_else(|| {
+                    anyhow!("Bad harmonics {s:?}: ranges need an inclusive end, like 1..=3")
+                })?;
+                Ok(Self {
+                    from: parse(from)?,
+                    to: Some(parse(to)?),
+                })
+            }
+        }
+    }
 }
 
 impl std::str::FromStr for Grid {
@@ -112,11 +668,11 @@ impl std::str::FromStr for Grid {
                     .insert(Position::new(row_idx as i32, col_idx as i32));
             }
         }
-        Ok(Self {
-            height,
-            width,
-            antennas,
-        })
+        let region = Region::Rectangle {
+            top_left: Position::new(0, 0),
+            bottom_right: Position::new(height as i32 - 1, width as i32 - 1),
+        };
+        Ok(Self { region, antennas })
     }
 }
 
@@ -135,6 +691,13 @@ impl Position {
     fn col(&self) -> i32 {
         self.0.y
     }
+
+    /// The position `n` lots of `step` away, unless that overflows.
+    fn checked_step(&self, step: IVec2, n: i32) -> Option<Position> {
+        let row = step.x.checked_mul(n)?.checked_add(self.row())?;
+        let col = step.y.checked_mul(n)?.checked_add(self.col())?;
+        Some(Position::new(row, col))
+    }
 }
 
 impl Ord for Position {
@@ -159,6 +722,12 @@ impl std::fmt::Debug for Position {
     }
 }
 
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.row(), self.col())
+    }
+}
+
 impl std::ops::Sub for &Position {
     type Output = IVec2;
 
@@ -189,7 +758,7 @@ mod test {
 
     use rstest::{fixture, rstest};
 
-    use super::{AntinodeMethod, Grid, Position};
+    use super::{AntinodeRule, Grid, Harmonics, Position, Region, Source};
 
     const SAMPLE_GRID_INPUT: &str = "\
 ..........
@@ -241,14 +810,18 @@ mod test {
         let a_positions = grid.antenna_positions('a').unwrap();
         let expected = HashSet::from([Position::new(3, 4), Position::new(5, 5)]);
         assert_eq!(a_positions, &expected);
-        assert_eq!((grid.height, grid.width), (10, 10));
+        let expected_region = Region::Rectangle {
+            top_left: Position::new(0, 0),
+            bottom_right: Position::new(9, 9),
+        };
+        assert_eq!(grid.region, expected_region);
         Ok(())
     }
 
     #[rstest]
     pub fn antinode_positions_for_a_part_one(sample_grid: &Grid) {
         let antinodes = sample_grid
-            .simple_antinode_positions_for_antenna('a')
+            .antinode_positions_for_antenna('a', &AntinodeRule::simple())
             .unwrap();
         let expected = HashSet::from([Position::new(1, 3), Position::new(7, 6)]);
         assert_eq!(antinodes, expected);
@@ -256,13 +829,293 @@ mod test {
 
     #[rstest]
     pub fn test_count_unique_antinode_positions_part_one(sample_large_grid: &Grid) {
-        let answer = sample_large_grid.count_antinode_positions(AntinodeMethod::Simple);
+        let answer = sample_large_grid.count_antinode_positions(&AntinodeRule::simple());
         assert_eq!(answer, 14);
     }
 
     #[rstest]
     pub fn test_count_unique_antinode_positions_part_two(sample_large_grid: &Grid) {
-        let answer = sample_large_grid.count_antinode_positions(AntinodeMethod::Resonant);
+        let answer = sample_large_grid.count_antinode_positions(&AntinodeRule::resonant());
         assert_eq!(answer, 34);
     }
+
+    #[test]
+    fn parse_harmonics() -> anyhow::Result<()> {
+        let one: Harmonics = "1".parse()?;
+        assert_eq!(one, AntinodeRule::simple().harmonics);
+        let open: Harmonics = "0..".parse()?;
+        assert_eq!(open, AntinodeRule::resonant().harmonics);
+        let range: Harmonics = "1..=3".parse()?;
+        assert_eq!((range.from, range.to), (1, Some(3)));
+        assert!("1..3".parse::<Harmonics>().is_err());
+        assert!("x".parse::<Harmonics>().is_err());
+        Ok(())
+    }
+
+    #[rstest]
+    fn harmonics_up_to_a_limit(sample_grid: &Grid) -> anyhow::Result<()> {
+        // The a antennas are two rows and a column apart, and two steps out
+        // from (3, 4) would be off the top of the grid.
+        let rule = AntinodeRule::simple().with_harmonics("1..=2".parse()?);
+        let antinodes = sample_grid
+            .antinode_positions_for_antenna('a', &rule)
+            .unwrap();
+        let expected = HashSet::from([
+            Position::new(1, 3),
+            Position::new(7, 6),
+            Position::new(9, 7),
+        ]);
+        assert_eq!(antinodes, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn between_without_reduce_uses_harmonic_ratios() -> anyhow::Result<()> {
+        let grid = Grid::from_coordinates("c,0,0\nc,3,0\nd,0,5\nd,2,5")?
+            .with_region("rect:0,0,9,9".parse()?);
+        let rule = AntinodeRule::simple().with_between(true);
+        // Three rows apart, the 2:1 points are a third and two thirds of the
+        // way across.
+        let antinodes = grid.antinode_positions_for_antenna('c', &rule).unwrap();
+        let expected = HashSet::from([
+            Position::new(1, 0),
+            Position::new(2, 0),
+            Position::new(6, 0),
+        ]);
+        assert_eq!(antinodes, expected);
+        // Two rows apart, nothing between is on the grid.
+        let antinodes = grid.antinode_positions_for_antenna('d', &rule).unwrap();
+        assert_eq!(antinodes, HashSet::from([Position::new(4, 5)]));
+        // Harmonic 2's 3:2 points need five rows.
+        let grid = Grid::from_coordinates("e,0,0\ne,5,0")?;
+        let rule = rule.with_harmonics("2..=2".parse()?);
+        let antinodes = grid.antinode_positions_for_antenna('e', &rule).unwrap();
+        let expected = HashSet::from([
+            Position::new(2, 0),
+            Position::new(3, 0),
+            Position::new(15, 0),
+            Position::new(-10, 0),
+        ]);
+        assert_eq!(antinodes, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn reduce_the_pair_difference() -> anyhow::Result<()> {
+        // Antennas two rows and four columns apart have grid points on their
+        // line every row, not just every other row.
+        let grid: Grid = "\
+b.........
+..........
+....b.....
+..........
+..........
+"
+        .parse()?;
+        let resonant = AntinodeRule::resonant();
+        let plain = grid.antinode_positions_for_antenna('b', &resonant).unwrap();
+        let expected = HashSet::from([
+            Position::new(0, 0),
+            Position::new(2, 4),
+            Position::new(4, 8),
+        ]);
+        assert_eq!(plain, expected);
+        let reduced = resonant.with_reduce(true);
+        let antinodes = grid.antinode_positions_for_antenna('b', &reduced).unwrap();
+        assert_eq!(antinodes.len(), 4);
+        assert!(antinodes.contains(&Position::new(3, 6)));
+        let everywhere = reduced.with_between(true);
+        let antinodes = grid
+            .antinode_positions_for_antenna('b', &everywhere)
+            .unwrap();
+        assert_eq!(antinodes.len(), 5);
+        assert!(antinodes.contains(&Position::new(1, 2)));
+        Ok(())
+    }
+
+    #[rstest]
+    fn render_part_one_antinodes(sample_large_grid: &Grid) {
+        let report = sample_large_grid.antinode_report(&AntinodeRule::simple());
+        let expected = "\
+......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.";
+        assert_eq!(sample_large_grid.render(&report).unwrap(), expected);
+        assert_eq!(report.sources.len(), 14);
+    }
+
+    #[rstest]
+    fn render_part_two_antinodes(sample_large_grid: &Grid) {
+        let report = sample_large_grid.antinode_report(&AntinodeRule::resonant());
+        let expected = "\
+##....#....#
+.#.#....0...
+..#.#0....#.
+..##...0....
+....0....#..
+.#...#A....#
+...#..#.....
+#....#.#....
+..#.....A...
+....#....A..
+.#........#.
+...#......##";
+        assert_eq!(sample_large_grid.render(&report).unwrap(), expected);
+        assert_eq!(report.sources.len(), 34);
+    }
+
+    #[rstest]
+    fn report_antinode_sources(sample_large_grid: &Grid) {
+        let report = sample_large_grid.antinode_report(&AntinodeRule::simple());
+        // The antinode under the top A antenna comes from the 0 antennas.
+        let under_a = Position::new(5, 6);
+        assert_eq!(report.frequencies_at(&under_a), ['0'].into());
+        // (1, 3) is both an A antinode and a 0 antinode.
+        let both = Position::new(1, 3);
+        assert_eq!(report.frequencies_at(&both), ['0', 'A'].into());
+        assert!(report.sources[&both].contains(&Source {
+            frequency: 'A',
+            pair: (Position::new(5, 6), Position::new(9, 9)),
+        }));
+        let counts = report.counts_by_frequency();
+        assert_eq!(counts[&'0'] + counts[&'A'], 14 + 1);
+    }
+
+    #[test]
+    fn parse_regions() -> anyhow::Result<()> {
+        assert_eq!("none".parse::<Region>()?, Region::Unbounded);
+        assert_eq!(
+            "rect:-2,0,5,7".parse::<Region>()?,
+            Region::Rectangle {
+                top_left: Position::new(-2, 0),
+                bottom_right: Position::new(5, 7),
+            }
+        );
+        let triangle: Region = "poly:0,0;0,4;4,0".parse()?;
+        assert_eq!(
+            triangle.bounding_box(),
+            Some((Position::new(0, 0), Position::new(4, 4)))
+        );
+        assert!("poly:0,0;1,1".parse::<Region>().is_err());
+        assert!("rect:0,0,1".parse::<Region>().is_err());
+        assert!("circle".parse::<Region>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn polygon_contains_edges_and_not_notches() -> anyhow::Result<()> {
+        // An L shape: the square from (0, 0) to (4, 4) missing its
+        // bottom-right quarter.
+        let l_shape: Region = "poly:0,0;0,4;2,4;2,2;4,2;4,0".parse()?;
+        assert!(l_shape.contains(&Position::new(1, 1)));
+        assert!(l_shape.contains(&Position::new(0, 4)));
+        assert!(l_shape.contains(&Position::new(3, 2)));
+        assert!(l_shape.contains(&Position::new(2, 3)));
+        assert!(!l_shape.contains(&Position::new(3, 3)));
+        assert!(!l_shape.contains(&Position::new(5, 1)));
+        assert!(l_shape.in_bounding_box(&Position::new(3, 3)));
+        Ok(())
+    }
+
+    #[test]
+    fn in_bounds_rejects_negative_positions() {
+        let grid: Grid = SAMPLE_GRID_INPUT.parse().unwrap();
+        assert!(!grid.in_bounds(&Position::new(-1, 3)));
+        assert!(!grid.in_bounds(&Position::new(3, i32::MIN)));
+        assert!(grid.in_bounds(&Position::new(9, 0)));
+    }
+
+    #[test]
+    fn parse_coordinates() -> anyhow::Result<()> {
+        let grid = Grid::from_coordinates("a,3,4\na, 5, 5\n\nB,-1000000,2000000\n")?;
+        assert_eq!(grid.region, Region::Unbounded);
+        assert_eq!(
+            grid.antenna_positions('a'),
+            Some(&HashSet::from([Position::new(3, 4), Position::new(5, 5)]))
+        );
+        assert!(grid.antenna_positions('B').is_some());
+        assert!(Grid::from_coordinates("ab,1,2").is_err());
+        assert!(Grid::from_coordinates("a,1").is_err());
+        assert!(Grid::from_coordinates("a,1,x").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn stream_antinodes_without_bounds() -> anyhow::Result<()> {
+        let grid = Grid::from_coordinates("a,0,0\na,1000000000,1000000000")?;
+        assert!(grid.check_finite(&AntinodeRule::resonant()).is_err());
+        let rule = AntinodeRule::resonant().with_harmonics("0..=5".parse()?);
+        grid.check_finite(&rule)?;
+        // Stepping past i32::MAX stops instead of wrapping round.
+        let antinodes: HashSet<Position> = grid.antinodes(&rule).map(|(p, _)| p).collect();
+        let expected = HashSet::from([
+            Position::new(0, 0),
+            Position::new(1_000_000_000, 1_000_000_000),
+            Position::new(2_000_000_000, 2_000_000_000),
+            Position::new(-1_000_000_000, -1_000_000_000),
+            Position::new(-2_000_000_000, -2_000_000_000),
+        ]);
+        assert_eq!(antinodes, expected);
+        assert!(grid.render(&grid.antinode_report(&rule)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn antennas_outside_the_region() -> anyhow::Result<()> {
+        let grid =
+            Grid::from_coordinates("a,-15,0\na,-10,0")?.with_region("rect:0,0,10,10".parse()?);
+        let antinodes: HashSet<Position> = grid
+            .antinodes(&AntinodeRule::resonant())
+            .map(|(p, _)| p)
+            .collect();
+        let expected = HashSet::from([
+            Position::new(0, 0),
+            Position::new(5, 0),
+            Position::new(10, 0),
+        ]);
+        assert_eq!(antinodes, expected);
+        let rule = AntinodeRule::simple().with_harmonics("1..=5".parse()?);
+        let antinodes: HashSet<Position> = grid.antinodes(&rule).map(|(p, _)| p).collect();
+        assert_eq!(antinodes, expected);
+        let rule = AntinodeRule::simple().with_harmonics("1..=2".parse()?);
+        assert_eq!(grid.antinodes(&rule).count(), 1);
+        Ok(())
+    }
+
+    #[rstest]
+    fn polygon_region_limits_antinodes(sample_large_grid: &Grid) -> anyhow::Result<()> {
+        // The top-left half of the map, cut along its diagonal.
+        let grid = sample_large_grid
+            .clone()
+            .with_region("poly:0,0;0,11;11,0".parse()?);
+        let report = grid.antinode_report(&AntinodeRule::resonant());
+        assert!(report.sources.keys().all(|p| p.row() + p.col() <= 11));
+        assert!(report.sources.contains_key(&Position::new(0, 0)));
+        assert!(!report.sources.contains_key(&Position::new(11, 11)));
+        let map = grid.render(&report)?;
+        assert_eq!(map.lines().last(), Some(".           "));
+        Ok(())
+    }
+
+    #[test]
+    fn stream_writes_antinodes_out() -> anyhow::Result<()> {
+        let options = ["--stream".to_owned()];
+        let mut out = Vec::new();
+        let summary = super::explore(SAMPLE_INPUT_LARGE, &options, &mut out)?;
+        let streamed = String::from_utf8(out)?;
+        // 14 antinodes, with (1, 3) made by both frequencies.
+        assert_eq!(streamed.lines().count(), 15);
+        assert!(streamed.starts_with('('));
+        assert!(summary.starts_with("15 antinodes, counting each pair"));
+        Ok(())
+    }
 }
@@ -1,3 +1,6 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
 use crate::util::Answer;
 
 pub fn solve(input: &str) -> anyhow::Result<String> {
@@ -107,30 +110,15 @@ impl DiskMap {
     }
 
     fn compact_files(&mut self) {
+        let mut free = FreeSpaces::new(&self.spaces);
         for file in self.files.iter_mut().rev() {
-            // For each file, starting at the end,
-            for space_index in 0..self.spaces.len() {
-                // find an appropriate space, starting at the front, such that:
-                let space = &mut self.spaces[space_index];
-                // it is placed before the file,
-                if space.start_pos > file.start_pos {
-                    break;
-                }
-                // it can accommodate the length of the file,
-                if space.length >= file.length {
-                    file.start_pos = space.start_pos;
-                    if space.length == file.length {
-                        // perfectly,
-                        self.spaces.remove(space_index);
-                    } else {
-                        // or with some remaining space.
-                        space.start_pos += file.length;
-                        space.length -= file.length;
-                    }
-                    break;
-                }
+            // For each file, starting at the end, move it to the leftmost
+            // space that can hold it, if that space is before the file.
+            if let Some(start_pos) = free.take_leftmost(file.length, file.start_pos) {
+                file.start_pos = start_pos;
             }
         }
+        self.spaces = free.into_spaces();
     }
 
     fn files_checksum(&self) -> usize {
@@ -147,6 +135,64 @@ impl DiskMap {
     }
 }
 
+/// The free spaces on a disk, as a min-heap of start positions for each
+/// length of space, so the leftmost space that fits a file is found by
+/// peeking at the heaps for every length at least as long as the file.
+#[derive(Debug, Clone)]
+struct FreeSpaces {
+    /// `by_length[n]` holds the start positions of spaces of length `n`.
+    by_length: Vec<BinaryHeap<Reverse<usize>>>,
+}
+
+impl FreeSpaces {
+    fn new(spaces: &[Space]) -> Self {
+        let longest = spaces.iter().map(|s| s.length).max().unwrap_or_default();
+        let mut by_length = vec![BinaryHeap::new(); longest + 1];
+        for space in spaces.iter().filter(|s| s.length > 0) {
+            by_length[space.length].push(Reverse(space.start_pos));
+        }
+        Self { by_length }
+    }
+
+    /// Use up `length` blocks at the start of the leftmost space that can
+    /// hold them and starts before `before`, returning where that space
+    /// started. What's left of the space goes back in as a shorter one.
+    fn take_leftmost(&mut self, length: usize, before: usize) -> Option<usize> {
+        if length == 0 {
+            // Nothing to move.
+            return None;
+        }
+        let (space_length, Reverse(start_pos)) = self
+            .by_length
+            .iter()
+            .enumerate()
+            .skip(length)
+            .filter_map(|(space_length, heap)| Some((space_length, *heap.peek()?)))
+            .max_by_key(|(_, start_pos)| *start_pos)
+            .filter(|(_, Reverse(start_pos))| *start_pos < before)?;
+        self.by_length[space_length].pop();
+        if space_length > length {
+            self.by_length[space_length - length].push(Reverse(start_pos + length));
+        }
+        Some(start_pos)
+    }
+
+    /// The spaces left, in order along the disk.
+    fn into_spaces(self) -> Vec<Space> {
+        let mut spaces: Vec<Space> = self
+            .by_length
+            .into_iter()
+            .enumerate()
+            .flat_map(|(length, heap)| {
+                heap.into_iter()
+                    .map(move |Reverse(start_pos)| Space { start_pos, length })
+            })
+            .collect();
+        spaces.sort_by_key(|s| s.start_pos);
+        spaces
+    }
+}
+
 impl std::str::FromStr for DiskMap {
     type Err = anyhow::Error;
 
@@ -167,7 +213,7 @@ impl std::str::FromStr for DiskMap {
         let mut ids = 0..;
         let mut start_pos = 0_usize;
 
-        for (is_file, length) in is_file_iter.zip(all_lengths.into_iter()) {
+        for (is_file, length) in is_file_iter.zip(all_lengths) {
             if is_file {
                 let file_id = ids.next().unwrap();
                 files.push(File {
@@ -256,6 +302,19 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_sector_compact_spaces() -> anyhow::Result<()> {
+        let mut map = SAMPLE_INPUT.parse::<DiskMap>()?;
+        map.compact_files();
+        // 00992111777.44.333....5555.6666.....8888..
+        // Only what's left of the original spaces is kept, not the
+        // blocks the moved files left behind.
+        let starts: Vec<usize> = map.spaces.iter().map(|s| s.start_pos).collect();
+        assert_eq!(starts, [14, 18, 21, 26, 31, 35]);
+        assert!(map.spaces.iter().all(|s| s.length == 1));
+        Ok(())
+    }
+
     #[test]
     fn test_sector_compact_checksum() -> anyhow::Result<()> {
         let mut dm = SAMPLE_INPUT.parse::<DiskMap>()?;
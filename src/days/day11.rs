@@ -67,7 +67,7 @@ fn blink(num: u64) -> (u64, Option<u64>) {
     }
 
     let n_digits = num.ilog10() + 1;
-    if n_digits % 2 == 0 {
+    if n_digits.is_multiple_of(2) {
         let divisor = 10_u64.pow(n_digits / 2);
         let upper = num / divisor;
         let lower = num % divisor;
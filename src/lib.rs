@@ -18,6 +18,7 @@ const PUZZLE_INPUT: &[&str] = &[
 ];
 
 type Solver = fn(&str) -> anyhow::Result<String>;
+type Explorer = fn(&str, &[String], &mut dyn std::io::Write) -> anyhow::Result<String>;
 
 #[tracing::instrument]
 pub fn run(day: usize) -> anyhow::Result<String> {
@@ -39,8 +40,36 @@ pub fn run(day: usize) -> anyhow::Result<String> {
     let Some(day_fn) = days.get(day - 1) else {
         return Err(anyhow!("Day {day} is not implemented yet."));
     };
-    let Some(input) = PUZZLE_INPUT.get(day - 1) else {
-        return Err(anyhow!("No input for day {day}."));
+    day_fn(puzzle_input(day)?)
+}
+
+/// Run a day's extra tooling (renderers, diagnostics and the like) rather
+/// than its solver, passing on the command-line options given after the day.
+/// Tooling that produces output as it goes, rather than all at the end,
+/// writes it to `out`.
+#[tracing::instrument(skip(out))]
+pub fn explore(
+    day: usize,
+    options: &[String],
+    out: &mut dyn std::io::Write,
+) -> anyhow::Result<String> {
+    assert_ne!(day, 0, "Day must be >= 1.");
+    let explorer: Explorer = match day {
+        2 => days::day02::explore,
+        3 => days::day03::explore,
+        4 => days::day04::explore,
+        5 => days::day05::explore,
+        6 => days::day06::explore,
+        7 => days::day07::explore,
+        8 => days::day08::explore,
+        _ => return Err(anyhow!("Day {day} has no extra options.")),
     };
-    day_fn(input)
+    explorer(puzzle_input(day)?, options, out)
+}
+
+fn puzzle_input(day: usize) -> anyhow::Result<&'static str> {
+    PUZZLE_INPUT
+        .get(day - 1)
+        .copied()
+        .ok_or_else(|| anyhow!("No input for day {day}."))
 }
@@ -4,12 +4,18 @@ fn main() -> anyhow::Result<()> {
     // Enable `tracing` logging.
     tracing_subscriber::fmt::init();
 
-    let arg = std::env::args().nth(1);
-    let Some(Ok(day)) = arg.map(|s| s.parse::<usize>()) else {
+    let mut args = std::env::args().skip(1);
+    let Some(Ok(day)) = args.next().map(|s| s.parse::<usize>()) else {
         return Err(anyhow!("You must give the day to run."));
     };
-    let solution = aoc_2024::run(day)?;
-    println!("{solution}");
+    // Any further arguments are options for the day's extra tooling.
+    let options: Vec<String> = args.collect();
+    let output = if options.is_empty() {
+        aoc_2024::run(day)?
+    } else {
+        aoc_2024::explore(day, &options, &mut std::io::stdout())?
+    };
+    println!("{output}");
 
     Ok(())
 }